@@ -0,0 +1,268 @@
+//! Local allow and deny lists for player cards.
+//!
+//! Cards on the deny list, or missing from the allow list while the allow
+//! list is enforced, are rejected without asking the server. Entries are UID
+//! prefixes, so a single entry can cover a whole batch of cards.
+//!
+//! Phones emulating a card present a random UID on every tap, which can never
+//! be known to the server. These are rejected as unsupported unless disabled.
+
+use crate::hardware::nfc::NfcUid;
+use crate::state::PersistedState;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// First byte of random single size UIDs per ISO 14443-3.
+const RANDOM_UID_TAG: u8 = 0x08;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AccessConfig {
+    /// Whether to reject cards presenting a random UID.
+    reject_random_uids: bool,
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            reject_random_uids: true,
+        }
+    }
+}
+
+/// Outcome of checking a card against the access lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Allowed,
+    Denied,
+    /// The card presents a random UID, which changes on every tap.
+    RandomUid,
+}
+
+/// Hex encoded prefix of a UID, written with or without colons.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct UidPrefix(String);
+
+impl UidPrefix {
+    fn matches(&self, nfc_uid: &NfcUid) -> bool {
+        hex::encode(nfc_uid.as_bytes()).starts_with(&self.0)
+    }
+}
+
+impl TryFrom<String> for UidPrefix {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        let prefix = value.replace(':', "").to_ascii_lowercase();
+
+        if prefix.is_empty() || prefix.len() > 20 || !prefix.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            bail!("invalid UID prefix: {}", value);
+        }
+
+        Ok(Self(prefix))
+    }
+}
+
+impl From<UidPrefix> for String {
+    fn from(prefix: UidPrefix) -> Self {
+        prefix.0
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct AccessState {
+    /// Whether only cards on the allow list are accepted.
+    allow_list_only: bool,
+    allowed: BTreeSet<UidPrefix>,
+    denied: BTreeSet<UidPrefix>,
+}
+
+#[derive(Debug)]
+pub struct AccessList {
+    config: AccessConfig,
+    state: PersistedState<AccessState>,
+}
+
+impl AccessList {
+    pub async fn new(data_path: &Path, config: AccessConfig) -> Result<Self> {
+        Ok(Self {
+            config,
+            state: PersistedState::new(data_path, "access", None).await?,
+        })
+    }
+
+    /// Checks whether the card may bloop. The deny list takes precedence over
+    /// the allow list.
+    pub fn check(&self, nfc_uid: &NfcUid) -> Access {
+        if self.config.reject_random_uids && is_random_uid(nfc_uid) {
+            return Access::RandomUid;
+        }
+
+        if self
+            .state
+            .denied
+            .iter()
+            .any(|prefix| prefix.matches(nfc_uid))
+        {
+            return Access::Denied;
+        }
+
+        if self.state.allow_list_only
+            && !self
+                .state
+                .allowed
+                .iter()
+                .any(|prefix| prefix.matches(nfc_uid))
+        {
+            return Access::Denied;
+        }
+
+        Access::Allowed
+    }
+
+    pub fn allow(&mut self, prefixes: Vec<UidPrefix>) -> Result<()> {
+        self.state.mutate(|state| state.allowed.extend(prefixes))
+    }
+
+    pub fn deny(&mut self, prefixes: Vec<UidPrefix>) -> Result<()> {
+        self.state.mutate(|state| state.denied.extend(prefixes))
+    }
+
+    /// Removes the prefixes from both lists, or clears both lists if no
+    /// prefixes are given.
+    pub fn remove(&mut self, prefixes: Vec<UidPrefix>) -> Result<()> {
+        self.state.mutate(|state| {
+            if prefixes.is_empty() {
+                state.allowed.clear();
+                state.denied.clear();
+                return;
+            }
+
+            for prefix in &prefixes {
+                state.allowed.remove(prefix);
+                state.denied.remove(prefix);
+            }
+        })
+    }
+
+    pub fn set_allow_list_only(&mut self, allow_list_only: bool) -> Result<()> {
+        self.state
+            .mutate(|state| state.allow_list_only = allow_list_only)
+    }
+}
+
+fn is_random_uid(nfc_uid: &NfcUid) -> bool {
+    let bytes = nfc_uid.as_bytes();
+    bytes.len() == 4 && bytes[0] == RANDOM_UID_TAG
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+
+    fn uid(hex: &str) -> NfcUid {
+        NfcUid::from_hex(hex).unwrap()
+    }
+
+    fn prefixes(values: &[&str]) -> Vec<UidPrefix> {
+        values
+            .iter()
+            .map(|value| value.to_string().try_into().unwrap())
+            .collect()
+    }
+
+    async fn access_list(data_path: &Path) -> AccessList {
+        AccessList::new(data_path, AccessConfig::default())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_prefixes_with_colons_in_any_case() {
+        assert_eq!(prefixes(&["04:A2:1F"]), prefixes(&["04a21f"]));
+        assert!(UidPrefix::try_from(String::new()).is_err());
+        assert!(UidPrefix::try_from("04:g2".to_string()).is_err());
+        assert!(UidPrefix::try_from("0".repeat(21)).is_err());
+    }
+
+    #[tokio::test]
+    async fn denies_matching_prefixes_over_allowed_ones() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut access_list = access_list(data_dir.path()).await;
+
+        access_list.allow(prefixes(&["04a2"])).unwrap();
+        access_list.deny(prefixes(&["04a21f"])).unwrap();
+
+        assert_eq!(access_list.check(&uid("04a21f33445566")), Access::Denied);
+        assert_eq!(access_list.check(&uid("04a2ff33445566")), Access::Allowed);
+        assert_eq!(access_list.check(&uid("04112233445566")), Access::Allowed);
+    }
+
+    #[tokio::test]
+    async fn enforces_allow_list_only_when_enabled() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut access_list = access_list(data_dir.path()).await;
+
+        access_list.allow(prefixes(&["04a2"])).unwrap();
+        access_list.set_allow_list_only(true).unwrap();
+
+        assert_eq!(access_list.check(&uid("04a21f33445566")), Access::Allowed);
+        assert_eq!(access_list.check(&uid("04112233445566")), Access::Denied);
+
+        access_list.set_allow_list_only(false).unwrap();
+        assert_eq!(access_list.check(&uid("04112233445566")), Access::Allowed);
+    }
+
+    #[tokio::test]
+    async fn removes_given_prefixes_or_everything() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let mut access_list = access_list(data_dir.path()).await;
+
+        access_list.deny(prefixes(&["04a2", "0411"])).unwrap();
+        access_list.remove(prefixes(&["04a2"])).unwrap();
+
+        assert_eq!(access_list.check(&uid("04a21f33445566")), Access::Allowed);
+        assert_eq!(access_list.check(&uid("04112233445566")), Access::Denied);
+
+        access_list.remove(Vec::new()).unwrap();
+        assert_eq!(access_list.check(&uid("04112233445566")), Access::Allowed);
+    }
+
+    #[tokio::test]
+    async fn loads_lists_from_state_file() {
+        let data_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            data_dir.path().join("access.state"),
+            "allow_list_only = true\nallowed = [\"04a2\"]\ndenied = [\"04A2:1F\"]\n",
+        )
+        .unwrap();
+
+        let access_list = access_list(data_dir.path()).await;
+
+        assert_eq!(access_list.check(&uid("04a21f33445566")), Access::Denied);
+        assert_eq!(access_list.check(&uid("04a2ff33445566")), Access::Allowed);
+        assert_eq!(access_list.check(&uid("04112233445566")), Access::Denied);
+    }
+
+    #[tokio::test]
+    async fn rejects_random_uids_unless_disabled() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let access_list = access_list(data_dir.path()).await;
+
+        assert_eq!(access_list.check(&uid("08123456")), Access::RandomUid);
+        assert_eq!(access_list.check(&uid("04123456")), Access::Allowed);
+        assert_eq!(access_list.check(&uid("08123456789abc")), Access::Allowed);
+
+        let config = AccessConfig {
+            reject_random_uids: false,
+        };
+        let access_list = AccessList::new(data_dir.path(), config).await.unwrap();
+
+        assert_eq!(access_list.check(&uid("08123456")), Access::Allowed);
+    }
+}
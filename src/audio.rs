@@ -1,50 +1,367 @@
+use crate::gestures::{ButtonAction, ButtonActionReceiver};
 use crate::hardware::asset::AssetLoader;
-use crate::hardware::buttons::{Button, ButtonReceiver};
+use crate::loudness::{self, LoudnessNormalizer};
 use crate::state::PersistedState;
-use anyhow::{bail, Error, Result};
-use bloop_client_framework::audio::{self, PlaybackHandle};
+use anyhow::{anyhow, bail, Error, Result};
+use async_trait::async_trait;
+use bloop_client_framework::audio;
 use rand_distr::weighted::WeightedAliasIndex;
 use rand_distr::Distribution;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::env;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
+use thiserror::Error;
 use tokio::select;
 use tokio::sync::{mpsc, Mutex};
+use tokio::time::Instant;
 use tokio_graceful_shutdown::{FutureExt, IntoSubsystem, SubsystemHandle};
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// Category of a sound, each with its own gain level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCategory {
+    Bloop,
+    Award,
+    Achievement,
+    System,
+}
 
-#[derive(Debug, Clone)]
-pub struct AudioPlayer {
-    volume: Arc<Mutex<f32>>,
+/// Commands accepted by the [`VolumeControlTask`].
+#[derive(Clone, Debug)]
+pub enum VolumeCommand {
+    Range(f32, f32),
+    Level(AudioCategory, f32),
+    Normalize(bool),
+    Curve(VolumeCurve, u32),
+}
+
+/// Maps volume steps to gain.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VolumeCurve {
+    /// Gain grows linearly with the step.
+    Linear,
+    /// Every step changes the gain by the same amount of decibels, spanning
+    /// the given range between the lowest audible and the highest step. The
+    /// lowest step is always silent.
+    Logarithmic(f32),
+    /// Gain is linearly interpolated between the given levels.
+    Table(Vec<f32>),
+}
+
+impl VolumeCurve {
+    fn gain(&self, step: u32, steps: u32) -> f32 {
+        let position = if steps == 0 {
+            1.0
+        } else {
+            step.min(steps) as f32 / steps as f32
+        };
+
+        match self {
+            Self::Linear => position,
+            Self::Logarithmic(_) if position == 0.0 => 0.0,
+            Self::Logarithmic(range_db) => 10f32.powf((position - 1.0) * range_db / 20.0),
+            Self::Table(levels) if levels.is_empty() => position,
+            Self::Table(levels) => {
+                let index = position * (levels.len() - 1) as f32;
+                let lower = index.floor() as usize;
+                let upper = index.ceil() as usize;
+                let fraction = index - lower as f32;
+
+                levels[lower] + (levels[upper] - levels[lower]) * fraction
+            }
+        }
+    }
+
+    /// Finds the step whose gain is closest to the given gain.
+    fn nearest_step(&self, gain: f32, steps: u32) -> u32 {
+        (0..=steps)
+            .min_by(|a, b| {
+                let a = (self.gain(*a, steps) - gain).abs();
+                let b = (self.gain(*b, steps) - gain).abs();
+                a.total_cmp(&b)
+            })
+            .unwrap_or(steps)
+    }
+
+    fn sanitized(self) -> Self {
+        match self {
+            Self::Linear => Self::Linear,
+            Self::Logarithmic(range_db) if range_db.is_finite() && range_db > 0.0 => {
+                Self::Logarithmic(range_db)
+            }
+            Self::Logarithmic(_) => Self::default(),
+            Self::Table(levels) => Self::Table(
+                levels
+                    .into_iter()
+                    .map(|level| level.clamp(0.0, 1.0))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Default for VolumeCurve {
+    fn default() -> Self {
+        Self::Logarithmic(40.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Volume {
+    master: f32,
+    levels: CategoryLevels,
+    normalize: bool,
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            levels: CategoryLevels::default(),
+            normalize: false,
+        }
+    }
+}
+
+impl Volume {
+    fn for_category(&self, category: AudioCategory) -> f32 {
+        self.master * self.levels.get(category)
+    }
+}
+
+/// Asset played to check the output device when no startup tone is
+/// configured.
+const OUTPUT_CHECK_ASSET: &str = "volume-change.mp3";
+
+/// Gain of the output check, low but audible, as the persisted volume is not
+/// loaded yet.
+const OUTPUT_CHECK_GAIN: f32 = 0.3;
+
+/// Minimum time between two volume change sounds, so repeated changes while
+/// a button is held do not stack up.
+const VOLUME_FEEDBACK_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// ALSA card name the default output device is expected to play on, e.g.
+    /// `sndrpihifiberry`.
+    device: Option<String>,
+    /// Asset played to check the output device at startup.
+    startup_tone: Option<PathBuf>,
+}
+
+/// Verifies that the ALSA card exists and is selected for the default output
+/// device, which is the only one the player opens.
+///
+/// The card is selected through the `ALSA_CARD` env variable of the service or
+/// the ALSA configuration. The latter cannot be verified, so only a warning is
+/// logged when the card is not the first one.
+fn check_card(asound_path: &Path, device: &str, alsa_card: Option<&str>) -> Result<()> {
+    let card = std::fs::read_link(asound_path.join(device))
+        .map_err(|_| anyhow!("audio device {} not found", device))?;
+    let card = card.to_string_lossy();
+    let index = card.trim_start_matches("card");
+
+    match alsa_card {
+        Some(selected) if selected != device && selected != index => {
+            bail!("ALSA_CARD selects card {} instead of {}", selected, device)
+        }
+        Some(_) => {}
+        None if index != "0" => {
+            warn!(
+                "audio device {} is card {}, make sure ALSA uses it as default device",
+                device, index
+            );
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Error)]
+pub enum PlayError {
+    /// The data package lacks the asset, or it could not be opened.
+    #[error("failed to open asset: {0:#}")]
+    MissingAsset(Error),
+    #[error("failed to play audio: {0:#}")]
+    Playback(Error),
+}
+
+/// Destination of the sounds of an [`AudioPlayer`], the output device outside
+/// of tests.
+#[async_trait]
+pub trait AudioOutput: Send + Sync {
+    /// Verifies that the output can play sounds.
+    async fn check(&self) -> Result<()>;
+
+    /// Plays a file to its end.
+    async fn play_file(
+        &self,
+        path: &Path,
+        category: AudioCategory,
+        volume: f32,
+    ) -> Result<(), PlayError>;
+
+    /// Plays an asset of the data package to its end.
+    async fn play_asset(
+        &self,
+        path: &Path,
+        category: AudioCategory,
+        volume: f32,
+    ) -> Result<(), PlayError>;
+}
+
+/// Plays sounds through the default output device.
+struct DeviceOutput {
     asset_loader: AssetLoader,
+    device: Option<String>,
+    check_asset: PathBuf,
+}
+
+impl DeviceOutput {
+    /// Opens the asset off the runtime thread; on the Pi this is a
+    /// synchronous SD card access.
+    async fn read_asset(
+        &self,
+        path: &Path,
+    ) -> Result<impl std::io::Read + std::io::Seek + Send + Sync + 'static> {
+        let asset_loader = self.asset_loader.clone();
+        let path = path.to_path_buf();
+
+        tokio::task::spawn_blocking(move || asset_loader.read_file(path)).await?
+    }
+}
+
+#[async_trait]
+impl AudioOutput for DeviceOutput {
+    /// Verifies the selected card and plays the check asset through the
+    /// output device.
+    async fn check(&self) -> Result<()> {
+        if let Some(device) = &self.device {
+            if cfg!(target_os = "linux") {
+                let alsa_card = env::var("ALSA_CARD").ok();
+                check_card(Path::new("/proc/asound"), device, alsa_card.as_deref())?;
+            }
+
+            info!("using audio device {}", device);
+        }
+
+        let reader = self.read_asset(&self.check_asset).await?;
+
+        audio::play_reader(reader, OUTPUT_CHECK_GAIN)
+            .await
+            .map_err(|error| anyhow!("{}", error))
+    }
+
+    async fn play_file(
+        &self,
+        path: &Path,
+        _category: AudioCategory,
+        volume: f32,
+    ) -> Result<(), PlayError> {
+        audio::play_file(path, volume)
+            .await
+            .map_err(|error| PlayError::Playback(anyhow!("{}", error)))
+    }
+
+    async fn play_asset(
+        &self,
+        path: &Path,
+        _category: AudioCategory,
+        volume: f32,
+    ) -> Result<(), PlayError> {
+        let reader = self
+            .read_asset(path)
+            .await
+            .map_err(PlayError::MissingAsset)?;
+
+        audio::play_reader(reader, volume)
+            .await
+            .map_err(|error| PlayError::Playback(anyhow!("{}", error)))
+    }
+}
+
+#[derive(Clone)]
+pub struct AudioPlayer {
+    volume: Arc<Mutex<Volume>>,
+    output: Arc<dyn AudioOutput>,
+    output_available: Arc<AtomicBool>,
+    normalizer: Arc<Mutex<LoudnessNormalizer>>,
     bloop_collection: Arc<AudioCollection>,
     award_collection: Arc<AudioCollection>,
+    last_volume_feedback: Arc<Mutex<Option<Instant>>>,
 }
 
 impl AudioPlayer {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(config: &AudioConfig, data_path: &Path) -> Result<Self> {
         let asset_loader = AssetLoader::new();
         let bloop_collection = AudioCollection::from_dir(&asset_loader, "bloops").await?;
         let award_collection = AudioCollection::from_dir(&asset_loader, "awards").await?;
+        let output = DeviceOutput {
+            asset_loader,
+            device: config.device.clone(),
+            check_asset: config
+                .startup_tone
+                .clone()
+                .unwrap_or_else(|| OUTPUT_CHECK_ASSET.into()),
+        };
+
+        Self::with_output(
+            Arc::new(output),
+            data_path,
+            bloop_collection,
+            award_collection,
+        )
+        .await
+    }
+
+    /// Creates a player for the given output, which is checked first.
+    pub async fn with_output(
+        output: Arc<dyn AudioOutput>,
+        data_path: &Path,
+        bloop_collection: AudioCollection,
+        award_collection: AudioCollection,
+    ) -> Result<Self> {
+        let output_available = match output.check().await {
+            Ok(()) => true,
+            Err(error) => {
+                error!("no usable audio output device: {}", error);
+                false
+            }
+        };
 
         Ok(Self {
-            volume: Arc::new(Mutex::new(1.0)),
-            asset_loader,
+            volume: Arc::new(Mutex::new(Volume::default())),
+            output,
+            output_available: Arc::new(AtomicBool::new(output_available)),
+            normalizer: Arc::new(Mutex::new(LoudnessNormalizer::new(data_path).await?)),
             bloop_collection: Arc::new(bloop_collection),
             award_collection: Arc::new(award_collection),
+            last_volume_feedback: Arc::new(Mutex::new(None)),
         })
     }
 
+    /// Whether the output device passed its check during initialization.
+    pub fn is_output_available(&self) -> bool {
+        self.output_available.load(Ordering::Relaxed)
+    }
+
     pub async fn play_bloop(&mut self) -> Result<()> {
         let path = self.bloop_collection.choose_random().clone();
-        self.play_file(path).await
+        self.play_file(path, AudioCategory::Bloop).await
     }
 
     pub async fn play_award(&mut self) -> Result<()> {
         let path = self.award_collection.choose_random().clone();
-        self.play_file(path).await
+        self.play_file(path, AudioCategory::Award).await
     }
 
     pub async fn play_error(&mut self) -> Result<()> {
@@ -55,62 +372,199 @@ impl AudioPlayer {
         self.play_asset("throttle.mp3").await
     }
 
-    pub async fn play_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+    /// Plays `unsupported-card.mp3`, falling back to the error sound for data
+    /// packages without it.
+    pub async fn play_unsupported_card(&mut self) -> Result<()> {
+        self.play_optional_asset("unsupported-card.mp3", Some("error.mp3"))
+            .await
+    }
+
+    /// Plays `reader-fault.mp3`, falling back to the error sound for data
+    /// packages without it.
+    pub async fn play_reader_fault(&mut self) -> Result<()> {
+        self.play_optional_asset("reader-fault.mp3", Some("error.mp3"))
+            .await
+    }
+
+    /// Plays `already-counted.mp3` for cards presented again right after being
+    /// counted. Data packages without it leave the cue silent.
+    pub async fn play_already_counted(&mut self) -> Result<()> {
+        self.play_optional_asset("already-counted.mp3", None).await
+    }
+
+    /// Plays an asset which older data packages lack, playing the fallback
+    /// asset in its place if there is one.
+    async fn play_optional_asset(&mut self, path: &str, fallback: Option<&str>) -> Result<()> {
+        if !self.is_output_available() {
+            return Ok(());
+        }
+
+        let volume = self.volume.lock().await.for_category(AudioCategory::System);
+
+        match self
+            .output
+            .play_asset(Path::new(path), AudioCategory::System, volume)
+            .await
+        {
+            Err(PlayError::MissingAsset(_)) => match fallback {
+                Some(fallback) => self.play_asset(fallback).await,
+                None => Ok(()),
+            },
+            result => {
+                log_playback(result);
+                Ok(())
+            }
+        }
+    }
+
+    /// Plays an achievement file from the audio cache, applying loudness
+    /// normalization when enabled.
+    pub async fn play_achievement<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        if !self.is_output_available() {
+            return Ok(());
+        }
+
+        let path = path.as_ref();
         let volume = *self.volume.lock().await;
-        play_logged(audio::play_file(path.as_ref(), volume)).await;
+        let mut gain = volume.for_category(AudioCategory::Achievement);
+
+        if volume.normalize {
+            match self.normalizer.lock().await.gain(path) {
+                Some(normalization_gain) => gain *= normalization_gain,
+                None => {
+                    // Only files cached before normalization was enabled lack
+                    // a gain, which is there for the next time.
+                    let audio_player = self.clone();
+                    let path = path.to_path_buf();
+                    tokio::spawn(async move { audio_player.measure_loudness(&path).await });
+                }
+            }
+        }
+
+        log_playback(
+            self.output
+                .play_file(path, AudioCategory::Achievement, gain)
+                .await,
+        );
+        Ok(())
+    }
+
+    /// Measures the loudness of a newly cached achievement file while
+    /// normalization is enabled, so playing it does not have to.
+    pub async fn measure_loudness(&self, path: &Path) {
+        if !self.volume.lock().await.normalize || self.normalizer.lock().await.gain(path).is_some()
+        {
+            return;
+        }
+
+        let result = match loudness::measure_gain(path).await {
+            Ok(gain) => self.normalizer.lock().await.insert(path, gain),
+            Err(error) => Err(error),
+        };
+
+        if let Err(error) = result {
+            warn!("failed to measure loudness: {}", error);
+        }
+    }
+
+    /// Drops the loudness of files evicted from the audio cache.
+    pub async fn forget_loudness(&self, paths: &[PathBuf]) {
+        if let Err(error) = self.normalizer.lock().await.forget(paths) {
+            warn!("failed to update loudness state: {}", error);
+        }
+    }
+
+    async fn play_file<P: AsRef<Path>>(&mut self, path: P, category: AudioCategory) -> Result<()> {
+        if !self.is_output_available() {
+            return Ok(());
+        }
+
+        let volume = self.volume.lock().await.for_category(category);
+        log_playback(self.output.play_file(path.as_ref(), category, volume).await);
         Ok(())
     }
 
     pub async fn play_asset<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
-        let volume = *self.volume.lock().await;
+        if !self.is_output_available() {
+            return Ok(());
+        }
 
-        match self.read_asset(path.as_ref()).await {
-            Ok(reader) => play_logged(audio::play_reader(reader, volume)).await,
-            Err(error) => error!("failed to play audio: {}", error),
+        let volume = self.volume.lock().await.for_category(AudioCategory::System);
+        log_playback(
+            self.output
+                .play_asset(path.as_ref(), AudioCategory::System, volume)
+                .await,
+        );
+        Ok(())
+    }
+
+    /// Plays the given assets back to back.
+    pub async fn play_sequence<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<()> {
+        for path in paths {
+            self.play_asset(path).await?;
         }
 
         Ok(())
     }
 
     async fn set_volume(&self, volume: f32, silent: bool) {
-        let volume = volume.clamp(0.0, 1.0);
-        *self.volume.lock().await = volume;
+        let volume = {
+            let mut current = self.volume.lock().await;
+            current.master = volume.clamp(0.0, 1.0);
+            current.for_category(AudioCategory::System)
+        };
 
-        if !silent {
-            match self.read_asset(Path::new("volume-change.mp3")).await {
-                Ok(reader) => audio::play_reader(reader, volume).detach(),
-                Err(error) => error!("failed to play audio: {}", error),
+        if silent || !self.is_output_available() {
+            return;
+        }
+
+        {
+            let mut last_feedback = self.last_volume_feedback.lock().await;
+            let now = Instant::now();
+
+            if last_feedback.is_some_and(|last| now - last < VOLUME_FEEDBACK_INTERVAL) {
+                return;
             }
+
+            *last_feedback = Some(now);
         }
-    }
 
-    /// Opens the asset off the runtime thread; on the Pi this is a
-    /// synchronous SD card access.
-    async fn read_asset(
-        &self,
-        path: &Path,
-    ) -> Result<impl std::io::Read + std::io::Seek + Send + Sync + 'static> {
-        let asset_loader = self.asset_loader.clone();
-        let path = path.to_path_buf();
+        // Plays in the background, so further volume changes are not held up.
+        let output = self.output.clone();
+        tokio::spawn(async move {
+            log_playback(
+                output
+                    .play_asset(
+                        Path::new("volume-change.mp3"),
+                        AudioCategory::System,
+                        volume,
+                    )
+                    .await,
+            );
+        });
+    }
 
-        tokio::task::spawn_blocking(move || asset_loader.read_file(path)).await?
+    async fn set_levels(&self, levels: CategoryLevels, normalize: bool) {
+        let mut volume = self.volume.lock().await;
+        volume.levels = levels;
+        volume.normalize = normalize;
     }
 }
 
-async fn play_logged(handle: PlaybackHandle) {
-    if let Err(error) = handle.await {
-        error!("failed to play audio: {}", error);
+fn log_playback(result: Result<(), PlayError>) {
+    if let Err(error) = result {
+        error!("{}", error);
     }
 }
 
 #[derive(Debug)]
-struct AudioCollection {
+pub struct AudioCollection {
     paths: Vec<PathBuf>,
     dist: WeightedAliasIndex<f64>,
 }
 
 impl AudioCollection {
-    pub async fn from_dir<P: AsRef<Path>>(
+    async fn from_dir<P: AsRef<Path>>(
         asset_loader: &AssetLoader,
         path: P,
     ) -> Result<AudioCollection> {
@@ -125,6 +579,12 @@ impl AudioCollection {
             bail!("path {} contains no mp3 files", path.as_ref().display());
         }
 
+        Self::new(paths)
+    }
+
+    /// Creates a collection of files, weighted by a `.[w=<weight>]` suffix of
+    /// their names.
+    pub fn new(paths: Vec<PathBuf>) -> Result<AudioCollection> {
         let mut weights: Vec<f64> = Vec::new();
         let weight_regex = Regex::new(r"\.\[w=(\d+(?:\.\d*)?)]\.mp3$")?;
 
@@ -153,38 +613,44 @@ impl AudioCollection {
 }
 
 pub struct VolumeControlTask {
-    range_update_rx: mpsc::Receiver<(f32, f32)>,
-    button_rx: ButtonReceiver,
+    command_rx: mpsc::Receiver<VolumeCommand>,
+    button_action_rx: ButtonActionReceiver,
     audio_player: AudioPlayer,
     state: PersistedState<VolumeState>,
+    /// Muting is not persisted, so the box never starts up silent.
+    muted: bool,
 }
 
 impl VolumeControlTask {
     pub async fn new(
-        range_rx: mpsc::Receiver<(f32, f32)>,
-        button_rx: ButtonReceiver,
+        command_rx: mpsc::Receiver<VolumeCommand>,
+        button_action_rx: ButtonActionReceiver,
         audio_player: AudioPlayer,
+        data_path: &Path,
     ) -> Result<Self> {
         let state =
-            PersistedState::<VolumeState>::new("volume", Some(Duration::from_secs(5))).await?;
-        audio_player.set_volume(state.current, true).await;
+            PersistedState::<VolumeState>::new(data_path, "volume", Some(Duration::from_secs(5)))
+                .await?;
+        audio_player.set_levels(state.levels, state.normalize).await;
+        audio_player.set_volume(state.gain(), true).await;
 
         Ok(Self {
-            range_update_rx: range_rx,
-            button_rx,
+            command_rx,
+            button_action_rx,
             audio_player,
             state,
+            muted: false,
         })
     }
 
     pub async fn listen(&mut self) -> Result<()> {
         loop {
             select! {
-                Some(button) = self.button_rx.recv() => {
-                    self.handle_button_press(&button).await?;
+                Ok(action) = self.button_action_rx.recv() => {
+                    self.handle_button_action(action).await?;
                 },
-                Some(range) = self.range_update_rx.recv() => {
-                    self.handle_range_update(range).await?;
+                Some(command) = self.command_rx.recv() => {
+                    self.handle_command(command).await?;
                 },
                 else => break,
             }
@@ -193,35 +659,101 @@ impl VolumeControlTask {
         Ok(())
     }
 
-    async fn handle_button_press(&mut self, button: &Button) -> Result<()> {
-        let delta = match button {
-            Button::VolumeUp => 0.05,
-            Button::VolumeDown => -0.05,
-        };
+    async fn handle_button_action(&mut self, action: ButtonAction) -> Result<()> {
+        let step = match action {
+            ButtonAction::VolumeUp => self.state.step.saturating_add(1),
+            ButtonAction::VolumeDown => self.state.step.saturating_sub(1),
+            ButtonAction::ToggleMute => {
+                self.muted = !self.muted;
+                self.apply_volume().await;
+
+                info!("muted: {}", self.muted);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+        .clamp(self.state.min_step(), self.state.max_step());
 
-        let volume = (self.state.current + delta).clamp(self.state.min, self.state.max);
-        self.state.mutate(|state| state.current = volume)?;
-        self.audio_player.set_volume(volume, false).await;
+        // Changing the volume unmutes.
+        self.muted = false;
+        self.state.mutate(|state| state.step = step)?;
+        self.apply_volume().await;
 
-        info!("volume set to {}", volume);
+        info!("volume set to step {} of {}", step, self.state.steps);
         Ok(())
     }
 
+    /// Applies the current volume, playing the volume change sound unless
+    /// muted.
+    async fn apply_volume(&self) {
+        if self.muted {
+            self.audio_player.set_volume(0.0, true).await;
+        } else {
+            self.audio_player.set_volume(self.state.gain(), false).await;
+        }
+    }
+
+    async fn handle_command(&mut self, command: VolumeCommand) -> Result<()> {
+        match command {
+            VolumeCommand::Range(min, max) => self.handle_range_update((min, max)).await,
+            VolumeCommand::Level(category, level) => {
+                let level = level.clamp(0.0, 1.0);
+                self.state
+                    .mutate(|state| state.levels.set(category, level))?;
+                self.audio_player
+                    .set_levels(self.state.levels, self.state.normalize)
+                    .await;
+
+                info!("{:?} level set to {}", category, level);
+                Ok(())
+            }
+            VolumeCommand::Normalize(normalize) => {
+                self.state.mutate(|state| state.normalize = normalize)?;
+                self.audio_player
+                    .set_levels(self.state.levels, self.state.normalize)
+                    .await;
+
+                info!("loudness normalization set to {}", normalize);
+                Ok(())
+            }
+            VolumeCommand::Curve(curve, steps) => self.handle_curve_update(curve, steps).await,
+        }
+    }
+
     async fn handle_range_update(&mut self, range: (f32, f32)) -> Result<()> {
         let min = range.0.clamp(0.0, 1.0);
         let max = range.1.clamp(min, 1.0);
-        let current = self.state.current.clamp(min, max);
 
         self.state.mutate(|state| {
-            state.min = range.0;
-            state.max = range.1;
-            state.current = current;
+            state.min = min;
+            state.max = max;
+            state.step = state.step.clamp(state.min_step(), state.max_step());
         })?;
-        self.audio_player.set_volume(current, false).await;
+        self.apply_volume().await;
 
         info!("volume range set to {} - {}", min, max);
         Ok(())
     }
+
+    async fn handle_curve_update(&mut self, curve: VolumeCurve, steps: u32) -> Result<()> {
+        let steps = steps.clamp(1, MAX_VOLUME_STEPS);
+        let curve = curve.sanitized();
+
+        self.state.mutate(|state| {
+            let position = state.step as f32 / state.steps as f32;
+            state.curve = curve;
+            state.steps = steps;
+            state.step = ((position * steps as f32).round() as u32)
+                .clamp(state.min_step(), state.max_step());
+        })?;
+        self.apply_volume().await;
+
+        info!(
+            "volume curve set to {:?} with {} steps",
+            self.state.curve, steps
+        );
+        Ok(())
+    }
 }
 
 impl IntoSubsystem<Error> for VolumeControlTask {
@@ -234,19 +766,101 @@ impl IntoSubsystem<Error> for VolumeControlTask {
     }
 }
 
-#[derive(Clone, Copy, Debug, Serialize)]
+/// Gain levels per [`AudioCategory`], applied on top of the master volume.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct CategoryLevels {
+    bloop: f32,
+    award: f32,
+    achievement: f32,
+    system: f32,
+}
+
+impl CategoryLevels {
+    fn get(&self, category: AudioCategory) -> f32 {
+        match category {
+            AudioCategory::Bloop => self.bloop,
+            AudioCategory::Award => self.award,
+            AudioCategory::Achievement => self.achievement,
+            AudioCategory::System => self.system,
+        }
+    }
+
+    fn set(&mut self, category: AudioCategory, level: f32) {
+        let slot = match category {
+            AudioCategory::Bloop => &mut self.bloop,
+            AudioCategory::Award => &mut self.award,
+            AudioCategory::Achievement => &mut self.achievement,
+            AudioCategory::System => &mut self.system,
+        };
+
+        *slot = level;
+    }
+
+    fn clamped(self) -> Self {
+        Self {
+            bloop: self.bloop.clamp(0.0, 1.0),
+            award: self.award.clamp(0.0, 1.0),
+            achievement: self.achievement.clamp(0.0, 1.0),
+            system: self.system.clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl Default for CategoryLevels {
+    fn default() -> Self {
+        Self {
+            bloop: 1.0,
+            award: 1.0,
+            achievement: 1.0,
+            system: 1.0,
+        }
+    }
+}
+
+const DEFAULT_VOLUME_STEPS: u32 = 20;
+const MAX_VOLUME_STEPS: u32 = 100;
+
+/// Persisted volume settings.
+///
+/// The volume is stored as a step index rather than a gain, so that it stays
+/// the same when the curve changes. `min` and `max` are positions between 0.0
+/// and 1.0 on the step range.
+#[derive(Clone, Debug, Serialize)]
 struct VolumeState {
-    current: f32,
+    step: u32,
+    steps: u32,
+    curve: VolumeCurve,
     min: f32,
     max: f32,
+    levels: CategoryLevels,
+    normalize: bool,
+}
+
+impl VolumeState {
+    fn gain(&self) -> f32 {
+        self.curve.gain(self.step, self.steps)
+    }
+
+    fn min_step(&self) -> u32 {
+        (self.min * self.steps as f32).round() as u32
+    }
+
+    fn max_step(&self) -> u32 {
+        ((self.max * self.steps as f32).round() as u32).max(self.min_step())
+    }
 }
 
 impl Default for VolumeState {
     fn default() -> Self {
         Self {
-            current: 1.0,
+            step: DEFAULT_VOLUME_STEPS,
+            steps: DEFAULT_VOLUME_STEPS,
+            curve: VolumeCurve::default(),
             min: 0.0,
             max: 1.0,
+            levels: CategoryLevels::default(),
+            normalize: false,
         }
     }
 }
@@ -258,19 +872,140 @@ impl<'de> Deserialize<'de> for VolumeState {
     {
         #[derive(Deserialize)]
         struct RawVolumeState {
-            current: f32,
+            step: Option<u32>,
+            /// Gain stored by versions before the step index was introduced.
+            current: Option<f32>,
+            steps: Option<u32>,
+            #[serde(default)]
+            curve: VolumeCurve,
             min: f32,
             max: f32,
+            #[serde(default)]
+            levels: CategoryLevels,
+            #[serde(default)]
+            normalize: bool,
         }
 
         let raw = RawVolumeState::deserialize(deserializer)?;
-        let min = raw.min.clamp(0.0, 1.0);
-        let max = raw.max.clamp(min, 1.0);
+        let steps = raw
+            .steps
+            .unwrap_or(DEFAULT_VOLUME_STEPS)
+            .clamp(1, MAX_VOLUME_STEPS);
+        let curve = raw.curve.sanitized();
+        let mut min = raw.min.clamp(0.0, 1.0);
+        let mut max = raw.max.clamp(min, 1.0);
+
+        // Versions before the step index stored the limits as gains as well.
+        if raw.step.is_none() {
+            min = curve.nearest_step(min, steps) as f32 / steps as f32;
+            max = curve.nearest_step(max, steps) as f32 / steps as f32;
+        }
 
-        Ok(Self {
-            current: raw.current.clamp(0.0, 1.0),
+        let step = match (raw.step, raw.current) {
+            (Some(step), _) => step,
+            (None, Some(current)) => curve.nearest_step(current.clamp(0.0, 1.0), steps),
+            (None, None) => steps,
+        };
+
+        let mut state = Self {
+            step,
+            steps,
+            curve,
             min,
             max,
-        })
+            levels: raw.levels.clamped(),
+            normalize: raw.normalize,
+        };
+        state.step = state.step.clamp(state.min_step(), state.max_step());
+
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{Event, Recorder};
+
+    #[test]
+    fn migrates_legacy_volume_gains() {
+        let state: VolumeState = toml::from_str("current = 0.5\nmin = 0.5\nmax = 0.5").unwrap();
+        let gain = state.gain();
+
+        assert!((gain - 0.5).abs() < 0.1, "gain {gain}");
+        assert_eq!(state.min_step(), state.step);
+        assert_eq!(state.max_step(), state.step);
+    }
+
+    #[test]
+    fn clamps_step_to_limits() {
+        let state: VolumeState =
+            toml::from_str("step = 20\nsteps = 20\nmin = 0.25\nmax = 0.5").unwrap();
+
+        assert_eq!(state.step, 10);
+    }
+
+    #[tokio::test]
+    async fn records_fallbacks_of_missing_assets() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let recorder = Recorder::new();
+        recorder.remove_asset("missing.mp3");
+        let mut audio_player = recorder.audio_player(data_dir.path()).await.unwrap();
+
+        audio_player
+            .play_optional_asset("missing.mp3", Some("error.mp3"))
+            .await
+            .unwrap();
+        audio_player
+            .play_optional_asset("missing.mp3", None)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            recorder.events(),
+            vec![Event::sound("error.mp3", AudioCategory::System)]
+        );
+    }
+
+    #[cfg(unix)]
+    fn asound_dir() -> tempfile::TempDir {
+        let asound_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(asound_dir.path().join("card0")).unwrap();
+        std::fs::create_dir(asound_dir.path().join("card1")).unwrap();
+        std::os::unix::fs::symlink("card0", asound_dir.path().join("vc4hdmi")).unwrap();
+        std::os::unix::fs::symlink("card1", asound_dir.path().join("sndrpihifiberry")).unwrap();
+        asound_dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fails_for_missing_card() {
+        let asound_dir = asound_dir();
+
+        assert!(check_card(asound_dir.path(), "wm8960", None).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accepts_card_selected_by_name_or_index() {
+        let asound_dir = asound_dir();
+
+        check_card(asound_dir.path(), "vc4hdmi", None).unwrap();
+        check_card(
+            asound_dir.path(),
+            "sndrpihifiberry",
+            Some("sndrpihifiberry"),
+        )
+        .unwrap();
+        check_card(asound_dir.path(), "sndrpihifiberry", Some("1")).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fails_for_other_selected_card() {
+        let asound_dir = asound_dir();
+
+        assert!(check_card(asound_dir.path(), "sndrpihifiberry", Some("vc4hdmi")).is_err());
+        assert!(check_card(asound_dir.path(), "sndrpihifiberry", Some("0")).is_err());
     }
 }
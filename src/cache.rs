@@ -0,0 +1,389 @@
+//! Disk quota and least-recently-played eviction for the achievement audio
+//! cache.
+//!
+//! The files themselves are managed by the framework's `AudioCache`; this
+//! module only keeps track of their sizes and play times and removes files
+//! when the cache grows beyond its quota.
+
+use crate::state::PersistedState;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+/// Files written by the framework's `AudioCache`.
+#[derive(Debug, PartialEq, Eq)]
+enum CacheFile {
+    /// Audio of an achievement, named `{id}_{hash}.mp3`.
+    Audio,
+    /// A download in progress or interrupted, named `{id}.{n}.download`.
+    Partial,
+}
+
+impl CacheFile {
+    fn from_name(name: &str) -> Option<Self> {
+        if let Some((id, counter)) = name
+            .strip_suffix(".download")
+            .and_then(|stem| stem.split_once('.'))
+        {
+            return (Uuid::parse_str(id).is_ok() && counter.parse::<u64>().is_ok())
+                .then_some(Self::Partial);
+        }
+
+        let (id, hash) = name.strip_suffix(".mp3")?.split_once('_')?;
+
+        (Uuid::parse_str(id).is_ok() && !hash.is_empty() && hex::decode(hash).is_ok())
+            .then_some(Self::Audio)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AudioCacheConfig {
+    /// Maximum size of the cache in megabytes.
+    quota_mb: u64,
+}
+
+impl Default for AudioCacheConfig {
+    fn default() -> Self {
+        Self { quota_mb: 128 }
+    }
+}
+
+#[derive(Debug)]
+pub struct CacheIndex {
+    path: PathBuf,
+    quota: u64,
+    state: PersistedState<CacheIndexState>,
+}
+
+impl CacheIndex {
+    /// Creates the index of the `cache` directory in the data dir.
+    pub async fn new(data_path: &Path, config: &AudioCacheConfig) -> Result<Self> {
+        let mut index = Self {
+            path: data_path.join("cache"),
+            quota: config.quota_mb * 1024 * 1024,
+            state: PersistedState::new(data_path, "cache-index", None).await?,
+        };
+
+        index.clean_up().await?;
+        index.enforce_quota(None).await?;
+
+        Ok(index)
+    }
+
+    /// Directory of the cached files.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records that a cached file was just played, returning the files
+    /// evicted to stay within the quota.
+    pub async fn touch(&mut self, path: &Path) -> Result<Vec<PathBuf>> {
+        let Some(key) = self.key(path) else {
+            return Ok(Vec::new());
+        };
+        let size = fs::metadata(path).await?.len();
+        let now = unix_time(SystemTime::now());
+
+        self.state.mutate(|state| {
+            state.entries.insert(
+                key.clone(),
+                CacheEntry {
+                    size,
+                    last_played: now,
+                },
+            );
+        })?;
+
+        self.enforce_quota(Some(&key)).await
+    }
+
+    /// Replaces the set of files referenced by the current audio manifest,
+    /// returning the files evicted to stay within the quota.
+    ///
+    /// These files are never evicted.
+    pub async fn protect(&mut self, paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let mut entries = Vec::new();
+
+        for path in paths {
+            let Some(key) = self.key(path) else {
+                continue;
+            };
+
+            match fs::metadata(path).await {
+                Ok(metadata) => entries.push((key, metadata.len())),
+                Err(error) => warn!("failed to stat {}: {}", path.display(), error),
+            }
+        }
+
+        let now = unix_time(SystemTime::now());
+
+        self.state.mutate(|state| {
+            state.protected = entries.iter().map(|(key, _)| key.clone()).collect();
+
+            for (key, size) in entries {
+                state
+                    .entries
+                    .entry(key)
+                    .and_modify(|entry| entry.size = size)
+                    .or_insert(CacheEntry {
+                        size,
+                        last_played: now,
+                    });
+            }
+        })?;
+
+        self.enforce_quota(None).await
+    }
+
+    /// Removes partially written files and index entries whose files are gone.
+    ///
+    /// Audio files unknown to the index, e.g. as it was lost, are adopted
+    /// rather than removed, as the saved audio manifest still refers to them.
+    /// Files not named by the framework are left alone.
+    #[instrument(skip(self))]
+    async fn clean_up(&mut self) -> Result<()> {
+        let mut found = HashMap::new();
+
+        for (path, metadata) in list_files(&self.path).await? {
+            let Some(key) = self.key(&path) else {
+                continue;
+            };
+            let Some(file) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(CacheFile::from_name)
+            else {
+                continue;
+            };
+
+            if file == CacheFile::Partial || metadata.len() == 0 {
+                info!("removing stale cache file {}", path.display());
+                remove_file(&path).await?;
+                continue;
+            }
+
+            let last_played = metadata.modified().map(unix_time).unwrap_or_default();
+            found.insert(
+                key,
+                CacheEntry {
+                    size: metadata.len(),
+                    last_played,
+                },
+            );
+        }
+
+        self.state.mutate(|state| {
+            state.entries.retain(|key, _| found.contains_key(key));
+
+            for (key, entry) in found {
+                state.entries.entry(key).or_insert(entry).size = entry.size;
+            }
+        })
+    }
+
+    /// Evicts the least recently played files until the cache fits into the
+    /// quota again, returning their paths.
+    async fn enforce_quota(&mut self, keep: Option<&str>) -> Result<Vec<PathBuf>> {
+        let mut total: u64 = self.state.entries.values().map(|entry| entry.size).sum();
+
+        if total <= self.quota {
+            return Ok(Vec::new());
+        }
+
+        let mut candidates = self
+            .state
+            .entries
+            .iter()
+            .filter(|(key, _)| !self.state.protected.contains(*key) && Some(key.as_str()) != keep)
+            .map(|(key, entry)| (key.clone(), *entry))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, entry)| entry.last_played);
+
+        let mut evicted = Vec::new();
+
+        for (key, entry) in candidates {
+            if total <= self.quota {
+                break;
+            }
+
+            remove_file(&self.path.join(&key)).await?;
+            total -= entry.size;
+            evicted.push(key);
+        }
+
+        if !evicted.is_empty() {
+            info!("evicted {} files from the audio cache", evicted.len());
+        }
+
+        self.state.mutate(|state| {
+            for key in &evicted {
+                state.entries.remove(key);
+            }
+        })?;
+
+        if total > self.quota {
+            warn!(
+                "audio cache uses {} bytes, exceeding its quota of {} bytes",
+                total, self.quota
+            );
+        }
+
+        Ok(evicted.iter().map(|key| self.path.join(key)).collect())
+    }
+
+    fn key(&self, path: &Path) -> Option<String> {
+        path.strip_prefix(&self.path)
+            .ok()
+            .map(|path| path.to_string_lossy().into_owned())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+struct CacheEntry {
+    size: u64,
+    last_played: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct CacheIndexState {
+    entries: HashMap<String, CacheEntry>,
+    protected: HashSet<String>,
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+async fn list_files(path: &Path) -> Result<Vec<(PathBuf, std::fs::Metadata)>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![path.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error.into()),
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push((entry.path(), metadata));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+async fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUDIO: &str = "00000000-0000-0000-0000-000000000001_00112233.mp3";
+    const OTHER_AUDIO: &str = "00000000-0000-0000-0000-000000000002_44556677.mp3";
+    const PARTIAL: &str = "00000000-0000-0000-0000-000000000001.0.download";
+
+    async fn cache_dir(files: &[&str]) -> (tempfile::TempDir, PathBuf) {
+        let data_dir = tempfile::tempdir().unwrap();
+        let path = data_dir.path().join("cache");
+        fs::create_dir_all(&path).await.unwrap();
+
+        for file in files {
+            fs::write(path.join(file), b"audio").await.unwrap();
+        }
+
+        (data_dir, path)
+    }
+
+    #[test]
+    fn recognizes_framework_files() {
+        assert_eq!(CacheFile::from_name(AUDIO), Some(CacheFile::Audio));
+        assert_eq!(CacheFile::from_name(PARTIAL), Some(CacheFile::Partial));
+        assert_eq!(CacheFile::from_name("achievement.mp3"), None);
+        assert_eq!(CacheFile::from_name("achievement.0.download"), None);
+        assert_eq!(
+            CacheFile::from_name("00000000-0000-0000-0000-000000000001_.mp3"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn removes_partial_downloads() {
+        let (data_dir, path) = cache_dir(&[AUDIO, PARTIAL]).await;
+
+        let index = CacheIndex::new(data_dir.path(), &AudioCacheConfig::default())
+            .await
+            .unwrap();
+
+        assert!(fs::try_exists(path.join(AUDIO)).await.unwrap());
+        assert!(!fs::try_exists(path.join(PARTIAL)).await.unwrap());
+        assert_eq!(index.state.entries.keys().collect::<Vec<_>>(), [AUDIO]);
+    }
+
+    #[tokio::test]
+    async fn leaves_foreign_files_alone() {
+        let (data_dir, path) = cache_dir(&["notes.txt", "achievement.mp3"]).await;
+
+        let index = CacheIndex::new(data_dir.path(), &AudioCacheConfig::default())
+            .await
+            .unwrap();
+
+        assert!(fs::try_exists(path.join("notes.txt")).await.unwrap());
+        assert!(fs::try_exists(path.join("achievement.mp3")).await.unwrap());
+        assert!(index.state.entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn evicts_all_but_the_played_file_beyond_quota() {
+        let (data_dir, path) = cache_dir(&[AUDIO, OTHER_AUDIO]).await;
+        let mut index = CacheIndex::new(data_dir.path(), &AudioCacheConfig { quota_mb: 1 })
+            .await
+            .unwrap();
+        index.quota = 0;
+
+        let evicted = index.touch(&path.join(OTHER_AUDIO)).await.unwrap();
+
+        assert_eq!(evicted, [path.join(AUDIO)]);
+        assert!(!fs::try_exists(path.join(AUDIO)).await.unwrap());
+        assert!(fs::try_exists(path.join(OTHER_AUDIO)).await.unwrap());
+        assert_eq!(
+            index.state.entries.keys().collect::<Vec<_>>(),
+            [OTHER_AUDIO]
+        );
+    }
+
+    #[tokio::test]
+    async fn keeps_protected_files_beyond_quota() {
+        let (data_dir, path) = cache_dir(&[AUDIO, OTHER_AUDIO]).await;
+        let mut index = CacheIndex::new(data_dir.path(), &AudioCacheConfig { quota_mb: 1 })
+            .await
+            .unwrap();
+        index.quota = 0;
+
+        index.protect(&[path.join(AUDIO)]).await.unwrap();
+
+        assert!(fs::try_exists(path.join(AUDIO)).await.unwrap());
+        assert!(!fs::try_exists(path.join(OTHER_AUDIO)).await.unwrap());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::access::AccessConfig;
+use crate::audio::AudioConfig;
+use crate::cache::AudioCacheConfig;
+use crate::engine::EngineConfig;
+use crate::gestures::GestureConfig;
+use crate::palette::Palette;
+use crate::rescan::RescanConfig;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use tracing::info;
+
+/// Hardware independent sections of the config file.
+///
+/// Hardware backends read their own sections from the same file.
+#[derive(Debug, Deserialize, Default)]
+pub struct Config {
+    #[serde(default)]
+    pub engine: EngineConfig,
+    #[serde(default)]
+    pub audio: AudioConfig,
+    #[serde(default)]
+    pub audio_cache: AudioCacheConfig,
+    #[serde(default)]
+    pub palette: Palette,
+    #[serde(default)]
+    pub gestures: GestureConfig,
+    #[serde(default)]
+    pub access: AccessConfig,
+    #[serde(default)]
+    pub rescan: RescanConfig,
+}
+
+/// Loads the config file, falling back to the default config if it does not
+/// exist.
+///
+/// The path defaults to `/etc/bloop-box.conf` and can be overridden through
+/// the `BLOOP_BOX_CONFIG` environment variable.
+pub fn load_config<T: DeserializeOwned + Default>() -> Result<T> {
+    let path: PathBuf = env::var("BLOOP_BOX_CONFIG")
+        .unwrap_or_else(|_| "/etc/bloop-box.conf".to_string())
+        .into();
+
+    let mut file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            info!(
+                "Config file {} not found, using default config",
+                path.display()
+            );
+            return Ok(T::default());
+        }
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to open {}", path.display()))?
+        }
+    };
+
+    let mut toml_config = String::new();
+    file.read_to_string(&mut toml_config)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: T = toml::from_str(&toml_config)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    Ok(config)
+}
@@ -1,20 +1,32 @@
-use crate::audio::AudioPlayer;
-use crate::hardware::data_path;
-use crate::hardware::led::{Color, LedController};
-use crate::hardware::nfc::{NfcReader, NfcUid};
+use crate::access::{Access, AccessConfig, AccessList, UidPrefix};
+use crate::audio::{AudioCategory, AudioPlayer, VolumeCommand, VolumeCurve};
+use crate::cache::{AudioCacheConfig, CacheIndex};
+use crate::gestures::{ButtonAction, ButtonActionReceiver};
+use crate::hardware::led::LedController;
+use crate::hardware::nfc::{NfcReader, NfcReaderHandle, NfcUid, ReaderHealth};
 use crate::hardware::system::{set_wifi_credentials, shutdown_system};
+use crate::network::{AudioDownloader, NetworkClient, NetworkStatus};
+use crate::palette::{Palette, StatusLed};
+use crate::playback::Playback;
+use crate::provisioning;
+use crate::rescan::{RescanConfig, RescanGuard, RescanResponse};
 use crate::state::PersistedState;
-use anyhow::{bail, Error, Result};
-use bloop_client_framework::{
-    AudioCache, BloopClient, ConnectionConfig, ConnectionStatus, PreloadOutcome, RequestError,
-};
+use crate::status::status_clips;
+use crate::watchdog::Watchdog;
+use anyhow::{bail, Context, Error, Result};
+use bloop_client_framework::{AudioCache, ConnectionConfig, PreloadOutcome, RequestError};
 use bloop_protocol::message::ErrorResponse;
-use bloop_protocol::{Capabilities, DataHash};
+use bloop_protocol::DataHash;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
+use std::future::{poll_fn, Future};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
 use std::time::Duration;
 use tokio::sync::{mpsc, watch};
-use tokio::time::sleep;
+use tokio::time::{sleep, sleep_until, Instant};
 use tokio::{join, select};
 use tokio_graceful_shutdown::{FutureExt, IntoSubsystem, SubsystemHandle};
 use tracing::{error, info, instrument, warn};
@@ -39,38 +51,74 @@ impl From<ConnectionState> for ConnectionConfig {
     }
 }
 
+/// Time for which the outcome of a card is shown before returning to the idle
+/// state.
+const OUTCOME_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct EngineConfig {
+    /// Hands achievement audio off to the playback queue instead of waiting
+    /// for it to finish, so the next card can be handled right away.
+    fast_lane: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct NetworkState {
     connection: Option<ConnectionState>,
 }
 
 pub struct EngineProps {
+    pub config: EngineConfig,
+    /// Directory the state of the engine is persisted to.
+    pub data_path: PathBuf,
     pub led_controller: LedController,
-    pub nfc_reader: NfcReader,
-    pub network_client: BloopClient,
+    pub nfc_readers: Vec<NfcReaderHandle>,
+    pub nfc_health_rx: mpsc::Receiver<ReaderHealth>,
+    pub network_client: Arc<dyn NetworkClient>,
     pub audio_player: AudioPlayer,
-    pub network_status: watch::Receiver<ConnectionStatus>,
-    pub volume_range_tx: mpsc::Sender<(f32, f32)>,
+    pub playback_tx: mpsc::Sender<Playback>,
+    pub network_status: watch::Receiver<NetworkStatus>,
+    pub volume_tx: mpsc::Sender<VolumeCommand>,
+    pub audio_cache_config: AudioCacheConfig,
+    pub palette: Palette,
+    pub button_action_rx: ButtonActionReceiver,
+    pub access_config: AccessConfig,
+    pub rescan_config: RescanConfig,
 }
 
 pub struct Engine {
+    fast_lane: bool,
     led_controller: LedController,
-    nfc_reader: NfcReader,
-    network_client: BloopClient,
+    readers: Vec<ReaderSlot>,
+    nfc_health_rx: mpsc::Receiver<ReaderHealth>,
+    /// IDs of the readers which are currently unresponsive.
+    faulty_readers: BTreeSet<String>,
+    watchdog: Watchdog,
+    network_client: Arc<dyn NetworkClient>,
     audio_player: AudioPlayer,
-    network_status: watch::Receiver<ConnectionStatus>,
+    playback_tx: mpsc::Sender<Playback>,
+    network_status: watch::Receiver<NetworkStatus>,
     audio_cache: AudioCache,
-    volume_range_tx: mpsc::Sender<(f32, f32)>,
+    cache_index: CacheIndex,
+    access_list: AccessList,
+    rescan_guard: RescanGuard,
+    volume_tx: mpsc::Sender<VolumeCommand>,
+    palette: Palette,
+    button_action_rx: ButtonActionReceiver,
+    /// Achievement audio files played by the last bloop.
+    last_achievement_audio: Vec<PathBuf>,
     state: PersistedState<EngineState>,
     network_state: PersistedState<NetworkState>,
 }
 
 impl Engine {
     pub async fn new(props: EngineProps) -> Result<Self> {
-        let state = PersistedState::new("engine", None).await?;
+        let state = PersistedState::new(&props.data_path, "engine", None).await?;
         let network_state: PersistedState<NetworkState> =
-            PersistedState::new("network", None).await?;
-        let audio_cache = AudioCache::new(data_path().await?.join("cache"));
+            PersistedState::new(&props.data_path, "network", None).await?;
+        let cache_index = CacheIndex::new(&props.data_path, &props.audio_cache_config).await?;
+        let audio_cache = AudioCache::new(cache_index.path().to_path_buf());
 
         if let Some(connection) = network_state.connection.clone() {
             props
@@ -80,13 +128,24 @@ impl Engine {
         }
 
         Ok(Self {
+            fast_lane: props.config.fast_lane,
             led_controller: props.led_controller,
-            nfc_reader: props.nfc_reader,
+            readers: props.nfc_readers.into_iter().map(ReaderSlot::new).collect(),
+            nfc_health_rx: props.nfc_health_rx,
+            faulty_readers: BTreeSet::new(),
+            watchdog: Watchdog::from_env(),
             network_client: props.network_client,
             audio_player: props.audio_player,
+            playback_tx: props.playback_tx,
             network_status: props.network_status,
-            volume_range_tx: props.volume_range_tx,
+            volume_tx: props.volume_tx,
+            palette: props.palette,
+            button_action_rx: props.button_action_rx,
+            last_achievement_audio: Vec::new(),
             audio_cache,
+            cache_index,
+            access_list: AccessList::new(&props.data_path, props.access_config).await?,
+            rescan_guard: RescanGuard::new(props.rescan_config),
             state,
             network_state,
         })
@@ -94,120 +153,289 @@ impl Engine {
 
     #[instrument(skip(self, subsys))]
     async fn process(&mut self, subsys: &SubsystemHandle) -> Result<()> {
+        // Every step runs under the watchdog guard, as playing audio, waiting
+        // for cards and preloading can take longer than the watchdog timeout.
+        let watchdog = self.watchdog;
+
         if self.state.config_nfc_uids.is_empty() {
-            self.add_config_uid().await?;
+            watchdog.guard(self.add_config_uid()).await?;
         }
 
+        self.set_idle_led().await?;
+        // Time at which the outcome of the last card stops being shown.
+        let mut idle_at: Option<Instant> = None;
+
         loop {
-            self.set_idle_led().await?;
+            watchdog.keep_alive();
 
             select! {
-                nfc_uid = self.nfc_reader.wait_for_card() => {
-                    self.handle_nfc_scan(nfc_uid?, subsys).await?;
+                _ = watchdog.due() => continue,
+                _ = sleep_until(idle_at.unwrap_or_else(Instant::now)), if idle_at.is_some() => {
+                    idle_at = None;
+                }
+                (index, event) = next_reader_event(&mut self.readers) => match event? {
+                    ReaderEvent::Card(nfc_uid) => {
+                        let outcome = watchdog.guard(self.handle_nfc_scan(index, nfc_uid, subsys)).await?;
+                        idle_at = outcome.then(|| Instant::now() + OUTCOME_DURATION);
+                    }
+                    ReaderEvent::Removed => self.card_removed(index),
+                },
+                Some(health) = self.nfc_health_rx.recv() => {
+                    watchdog.guard(self.handle_reader_health(health)).await?;
                 }
                 _ = self.network_status.changed() => {
-                    self.handle_network_status_change().await?;
+                    watchdog.guard(self.handle_network_status_change()).await?;
+                }
+                Ok(action) = self.button_action_rx.recv() => {
+                    watchdog.guard(self.handle_button_action(action, subsys)).await?;
+
+                    // The volume flash returns to the current state by itself.
+                    if action.changes_volume() {
+                        continue;
+                    }
                 }
             }
+
+            if idle_at.is_none() {
+                self.set_idle_led().await?;
+            }
         }
     }
 
-    #[instrument(skip(self, nfc_uid, subsys))]
-    async fn handle_nfc_scan(&mut self, nfc_uid: NfcUid, subsys: &SubsystemHandle) -> Result<()> {
+    #[instrument(skip(self, subsys))]
+    async fn handle_button_action(
+        &mut self,
+        action: ButtonAction,
+        subsys: &SubsystemHandle,
+    ) -> Result<()> {
+        match action {
+            ButtonAction::StatusReadout => {
+                self.set_status_led(StatusLed::Busy).await?;
+                self.play_status_readout().await
+            }
+            ButtonAction::Shutdown => self.shutdown(subsys).await,
+            ButtonAction::Replay => {
+                self.set_status_led(StatusLed::Busy).await?;
+
+                for path in self.last_achievement_audio.clone() {
+                    self.audio_player.play_achievement(path).await?;
+                }
+
+                Ok(())
+            }
+            // The volume itself is changed by the volume control.
+            ButtonAction::VolumeUp | ButtonAction::VolumeDown | ButtonAction::ToggleMute => {
+                self.set_status_led(StatusLed::VolumeChange).await
+            }
+        }
+    }
+
+    async fn play_status_readout(&mut self) -> Result<()> {
+        let network_status = *self.network_status.borrow();
+        let clips = status_clips(network_status).await;
+        self.audio_player.play_sequence(&clips).await
+    }
+
+    async fn shutdown(&mut self, subsys: &SubsystemHandle) -> Result<()> {
+        self.network_client.shutdown().await;
+        shutdown_system().await?;
+        subsys.request_shutdown();
+        info!("system shutdown requested");
+
+        Ok(())
+    }
+
+    fn reader(&self, index: usize) -> &NfcReader {
+        &self.readers[index].handle.reader
+    }
+
+    /// Handles a card presented to the reader, returning whether an outcome
+    /// is shown for it.
+    #[instrument(skip(self, index, nfc_uid, subsys), fields(reader = %self.readers[index].handle.id))]
+    async fn handle_nfc_scan(
+        &mut self,
+        index: usize,
+        nfc_uid: NfcUid,
+        subsys: &SubsystemHandle,
+    ) -> Result<bool> {
         info!("handling nfc scan: {}", hex::encode(nfc_uid.as_bytes()));
-        self.led_controller.set_static(Color::Magenta).await?;
+        self.set_status_led(StatusLed::Busy).await?;
 
         if self.state.config_nfc_uids.contains(&nfc_uid) {
-            self.led_controller.set_static(Color::Magenta).await?;
-
-            match self.handle_config_command(nfc_uid, subsys).await {
+            match self.handle_config_command(index, nfc_uid, subsys).await {
                 Ok(()) => {
-                    self.led_controller.set_static(Color::Cyan).await?;
+                    self.set_status_led(StatusLed::ConfigOk).await?;
                 }
                 Err(err) => {
                     error!("error handling config card: {}", err);
-                    self.led_controller.set_static(Color::Red).await?;
+                    self.set_status_led(StatusLed::ConfigError).await?;
                 }
             }
 
             sleep(Duration::from_millis(500)).await;
-            self.nfc_reader.wait_for_removal().await?;
-            return Ok(());
+            self.wait_for_removal(index).await?;
+            return Ok(true);
+        }
+
+        match self.access_list.check(&nfc_uid) {
+            Access::Allowed => {}
+            Access::Denied => {
+                info!("NFC UID rejected by local access list");
+                self.set_status_led(StatusLed::BloopRejected).await?;
+                self.audio_player.play_error().await?;
+                self.readers[index].awaiting_removal = true;
+                return Ok(true);
+            }
+            Access::RandomUid => {
+                info!("random NFC UID rejected as unsupported card");
+                self.set_status_led(StatusLed::UnsupportedCard).await?;
+                self.audio_player.play_unsupported_card().await?;
+                self.readers[index].awaiting_removal = true;
+                return Ok(true);
+            }
+        }
+
+        if let Some(response) = self.rescan_guard.check(&nfc_uid) {
+            info!("NFC UID presented again, already counted");
+
+            self.readers[index].awaiting_removal = true;
+            self.readers[index].counted_uid = Some(nfc_uid);
+
+            if response == RescanResponse::Ignore {
+                return Ok(false);
+            }
+
+            self.set_status_led(StatusLed::BloopAccepted).await?;
+            self.audio_player.play_already_counted().await?;
+            return Ok(true);
         }
 
         if !matches!(
             *self.network_status.borrow(),
-            ConnectionStatus::Connected { .. }
+            NetworkStatus::Connected { .. }
         ) {
-            self.nfc_reader.wait_for_removal().await?;
-            return Ok(());
+            self.readers[index].awaiting_removal = true;
+            return Ok(true);
         }
 
-        self.handle_bloop(nfc_uid).await
+        if self.handle_bloop(nfc_uid).await? {
+            self.readers[index].counted_uid = Some(nfc_uid);
+        }
+
+        self.readers[index].awaiting_removal = true;
+        Ok(true)
     }
 
-    #[instrument(skip(self, nfc_uid))]
-    async fn handle_bloop(&mut self, nfc_uid: NfcUid) -> Result<()> {
-        self.led_controller.set_static(Color::Magenta).await?;
+    fn card_removed(&mut self, index: usize) {
+        let slot = &mut self.readers[index];
+        slot.awaiting_removal = false;
 
+        if let Some(nfc_uid) = slot.counted_uid.take() {
+            self.rescan_guard.removed(nfc_uid);
+        }
+    }
+
+    /// Sends the bloop and plays the response, returning whether the card was
+    /// counted.
+    #[instrument(skip(self, nfc_uid))]
+    async fn handle_bloop(&mut self, nfc_uid: NfcUid) -> Result<bool> {
         let (bloop_response, _) = join!(
             self.network_client.bloop(nfc_uid),
             self.audio_player.play_bloop(),
         );
 
+        let counted = bloop_response.is_ok();
+
         match bloop_response {
             Ok(achievements) => {
                 info!("NFC UID accepted, achievements awarded: {:?}", achievements);
+                self.set_status_led(StatusLed::BloopAccepted).await?;
+                self.last_achievement_audio.clear();
+                let mut playback = Vec::with_capacity(achievements.len());
 
                 for achievement in achievements {
-                    self.audio_player.play_award().await?;
+                    if !self.fast_lane {
+                        if self.palette.achievement_pulse {
+                            self.set_status_led(StatusLed::Achievement).await?;
+                        }
 
-                    match self
+                        self.audio_player.play_award().await?;
+                    }
+
+                    let path = match self
                         .audio_cache
-                        .ensure(&self.network_client, &achievement)
+                        .ensure(AudioDownloader(&*self.network_client), &achievement)
                         .await
                     {
-                        Ok(Some(path)) => self.audio_player.play_file(path).await?,
-                        Ok(None) => continue,
+                        Ok(Some(path)) => {
+                            self.audio_player.measure_loudness(&path).await;
+
+                            match self.cache_index.touch(&path).await {
+                                Ok(evicted) => self.audio_player.forget_loudness(&evicted).await,
+                                Err(error) => {
+                                    warn!("failed to update audio cache index: {}", error)
+                                }
+                            }
+
+                            self.last_achievement_audio.push(path.clone());
+                            Some(path)
+                        }
+                        Ok(None) => None,
                         Err(error) => {
                             warn!(
                                 "failed to load audio for achievement {}: {}",
                                 achievement.id, error
                             );
-                            continue;
+                            None
                         }
+                    };
+
+                    if self.fast_lane {
+                        playback.push(path);
+                    } else if let Some(path) = path {
+                        self.audio_player.play_achievement(path).await?;
+                    }
+                }
+
+                if !playback.is_empty() {
+                    // Leaves the audio playing while the next card is handled.
+                    if let Err(error) = self.playback_tx.try_send(playback) {
+                        warn!("dropping achievement audio: {}", error);
                     }
                 }
             }
 
             Err(RequestError::Error(ErrorResponse::NfcUidThrottled)) => {
                 info!("NFC UID throttled");
+                self.set_status_led(StatusLed::BloopThrottled).await?;
                 self.audio_player.play_throttled().await?;
             }
 
             Err(RequestError::Error(ErrorResponse::UnknownNfcUid)) => {
                 info!("NFC UID rejected");
+                self.set_status_led(StatusLed::BloopRejected).await?;
                 self.audio_player.play_error().await?;
             }
 
             Err(error) => {
                 warn!("bloop failed: {}", error);
+                self.set_status_led(StatusLed::BloopFailed).await?;
                 self.audio_player.play_error().await?;
             }
         }
 
-        self.nfc_reader.wait_for_removal().await?;
-        Ok(())
+        Ok(counted)
     }
 
-    #[instrument(skip(self, nfc_uid, subsys))]
+    #[instrument(skip(self, index, nfc_uid, subsys))]
     async fn handle_config_command(
         &mut self,
+        index: usize,
         nfc_uid: NfcUid,
         subsys: &SubsystemHandle,
     ) -> Result<()> {
         info!("handling config card");
-        let mut data = self.nfc_reader.read_ndef_text().await?;
+        let mut data = self.reader(index).read_ndef_text().await?;
 
         if data.is_empty() {
             bail!("empty card data");
@@ -244,11 +472,49 @@ impl Engine {
                 info!("connection details set");
             }
             'v' => {
-                let range: (f32, f32) = serde_json::from_str(data.as_str())?;
-                self.volume_range_tx.send(range).await?;
+                let (min, max): (f32, f32) = serde_json::from_str(data.as_str())?;
+                self.volume_tx.send(VolumeCommand::Range(min, max)).await?;
+            }
+            'k' => {
+                let (curve, steps): (VolumeCurve, u32) = serde_json::from_str(data.as_str())?;
+                self.volume_tx
+                    .send(VolumeCommand::Curve(curve, steps))
+                    .await?;
+            }
+            'g' => {
+                let (category, level): (AudioCategory, f32) = serde_json::from_str(data.as_str())?;
+                self.volume_tx
+                    .send(VolumeCommand::Level(category, level))
+                    .await?;
+            }
+            'n' => {
+                let (normalize,): (bool,) = serde_json::from_str(data.as_str())?;
+                self.volume_tx
+                    .send(VolumeCommand::Normalize(normalize))
+                    .await?;
+            }
+            'a' => {
+                let prefixes: Vec<UidPrefix> = serde_json::from_str(data.as_str())?;
+                self.access_list.allow(prefixes)?;
+                info!("UID prefixes added to allow list");
+            }
+            'd' => {
+                let prefixes: Vec<UidPrefix> = serde_json::from_str(data.as_str())?;
+                self.access_list.deny(prefixes)?;
+                info!("UID prefixes added to deny list");
+            }
+            'x' => {
+                let prefixes: Vec<UidPrefix> = serde_json::from_str(data.as_str())?;
+                self.access_list.remove(prefixes)?;
+                info!("UID prefixes removed from access lists");
+            }
+            'l' => {
+                let (allow_list_only,): (bool,) = serde_json::from_str(data.as_str())?;
+                self.access_list.set_allow_list_only(allow_list_only)?;
+                info!("allow list enforcement set to {}", allow_list_only);
             }
             'u' => {
-                self.nfc_reader.wait_for_removal().await?;
+                self.wait_for_removal(index).await?;
                 self.add_config_uid().await?;
             }
             'r' => {
@@ -258,25 +524,42 @@ impl Engine {
                 })?;
                 info!("config cards reset");
             }
-            's' => {
-                self.network_client.clone().shutdown().await;
-                shutdown_system().await?;
-                subsys.request_shutdown();
-                info!("system shutdown requested");
+            'p' => {
+                self.wait_for_removal(index).await?;
+                self.write_config_card().await?;
             }
+            'i' => self.play_status_readout().await?,
+            's' => self.shutdown(subsys).await?,
             command => bail!("unknown command: {}", command),
         }
 
         Ok(())
     }
 
+    async fn handle_reader_health(&mut self, health: ReaderHealth) -> Result<()> {
+        if health.healthy {
+            self.faulty_readers.remove(&health.reader_id);
+        } else if self.faulty_readers.insert(health.reader_id) {
+            self.set_status_led(StatusLed::ReaderFault).await?;
+            self.audio_player.play_reader_fault().await?;
+        }
+
+        let fault = (!self.faulty_readers.is_empty()).then(|| {
+            let ids: Vec<_> = self.faulty_readers.iter().map(String::as_str).collect();
+            format!("NFC reader {} unresponsive", ids.join(", "))
+        });
+
+        self.watchdog.set_fault(fault.as_deref());
+        Ok(())
+    }
+
     async fn handle_network_status_change(&mut self) -> Result<()> {
         let status = *self.network_status.borrow_and_update();
         info!("network status changed to {status:?}");
 
-        if let ConnectionStatus::Connected { capabilities, .. } = status {
-            if capabilities.contains(Capabilities::PreloadCheck) {
-                self.led_controller.set_breathing(Color::Cyan).await?;
+        if let NetworkStatus::Connected { preload_check } = status {
+            if preload_check {
+                self.set_status_led(StatusLed::Preloading).await?;
                 self.preload().await?;
             }
         }
@@ -285,9 +568,9 @@ impl Engine {
     }
 
     async fn add_config_uid(&mut self) -> Result<()> {
-        self.led_controller.set_breathing(Color::Magenta).await?;
-        let nfc_uid = self.nfc_reader.wait_for_card().await?;
-        self.nfc_reader.wait_for_removal().await?;
+        self.set_status_led(StatusLed::AwaitingConfigCard).await?;
+        let (index, nfc_uid) = self.wait_for_any_card().await?;
+        self.wait_for_removal(index).await?;
 
         self.state.mutate(|state| {
             state.config_nfc_uids.insert(nfc_uid);
@@ -297,6 +580,46 @@ impl Engine {
         Ok(())
     }
 
+    /// Writes the payload prepared through `bloop-box provision` to the next
+    /// card presented, which has to be blank.
+    async fn write_config_card(&mut self) -> Result<()> {
+        let record = provisioning::load()
+            .await?
+            .context("no provisioning payload prepared")?;
+
+        self.set_status_led(StatusLed::AwaitingBlankCard).await?;
+        let (index, nfc_uid) = self.wait_for_any_card().await?;
+        self.set_status_led(StatusLed::Busy).await?;
+        let result = self.readers[index].handle.writer.write(record).await;
+
+        if result.is_ok() {
+            info!("config card {} written", hex::encode(nfc_uid.as_bytes()));
+        }
+
+        // Keeps the written card from being read as a bloop.
+        self.wait_for_removal(index).await?;
+        result
+    }
+
+    /// Waits for the card to be removed from a reader, which is up to the
+    /// participant.
+    async fn wait_for_removal(&self, index: usize) -> Result<()> {
+        Ok(self.reader(index).wait_for_removal().await?)
+    }
+
+    /// Waits for a card on any reader, noting removals from readers awaiting
+    /// them in the meantime.
+    async fn wait_for_any_card(&mut self) -> Result<(usize, NfcUid)> {
+        loop {
+            let (index, event) = next_reader_event(&mut self.readers).await;
+
+            match event? {
+                ReaderEvent::Card(nfc_uid) => return Ok((index, nfc_uid)),
+                ReaderEvent::Removed => self.card_removed(index),
+            }
+        }
+    }
+
     #[instrument(skip(self))]
     async fn preload(&mut self) -> Result<()> {
         info!("starting audio preload");
@@ -324,13 +647,39 @@ impl Engine {
 
         match self
             .audio_cache
-            .sync(&self.network_client, &achievements)
+            .sync(AudioDownloader(&*self.network_client), &achievements)
             .await
         {
             Ok(skipped) if skipped.is_empty() => {
                 info!("audio preload succeeded");
                 self.state
                     .mutate(|state| state.audio_manifest_hash = Some(audio_manifest_hash))?;
+
+                // Files of the current manifest are protected from eviction.
+                let mut paths = Vec::new();
+
+                for achievement in &achievements {
+                    match self
+                        .audio_cache
+                        .ensure(AudioDownloader(&*self.network_client), achievement)
+                        .await
+                    {
+                        Ok(Some(path)) => {
+                            self.audio_player.measure_loudness(&path).await;
+                            paths.push(path);
+                        }
+                        Ok(None) => continue,
+                        Err(error) => {
+                            warn!(
+                                "failed to locate audio for achievement {}: {}",
+                                achievement.id, error
+                            );
+                        }
+                    }
+                }
+
+                let evicted = self.cache_index.protect(&paths).await?;
+                self.audio_player.forget_loudness(&evicted).await;
             }
             Ok(skipped) => {
                 info!("audio preload partial, {} files skipped", skipped.len());
@@ -343,24 +692,39 @@ impl Engine {
         Ok(())
     }
 
+    async fn set_status_led(&mut self, state: StatusLed) -> Result<()> {
+        self.led_controller
+            .set_animation(self.palette.animation(state))
+            .await?;
+
+        Ok(())
+    }
+
     async fn set_idle_led(&mut self) -> Result<()> {
+        if !self.faulty_readers.is_empty() {
+            return self.set_status_led(StatusLed::ReaderFault).await;
+        }
+
         let network_status = *self.network_status.borrow();
 
         match network_status {
-            ConnectionStatus::Connected { .. } => {
-                self.led_controller.set_static(Color::Green).await?;
+            NetworkStatus::Shutdown => {
+                self.led_controller.set_off().await?;
             }
-            ConnectionStatus::Unconfigured => {
-                self.led_controller.set_breathing(Color::Yellow).await?;
+            _ if !self.audio_player.is_output_available() => {
+                self.set_status_led(StatusLed::AudioUnavailable).await?;
             }
-            ConnectionStatus::InvalidCredentials => {
-                self.led_controller.set_breathing(Color::Red).await?;
+            NetworkStatus::Connected { .. } => {
+                self.set_status_led(StatusLed::IdleConnected).await?;
             }
-            ConnectionStatus::Shutdown => {
-                self.led_controller.set_off().await?;
+            NetworkStatus::Unconfigured => {
+                self.set_status_led(StatusLed::Unconfigured).await?;
+            }
+            NetworkStatus::InvalidCredentials => {
+                self.set_status_led(StatusLed::InvalidCredentials).await?;
             }
             _ => {
-                self.led_controller.set_breathing(Color::Blue).await?;
+                self.set_status_led(StatusLed::Offline).await?;
             }
         }
 
@@ -368,6 +732,73 @@ impl Engine {
     }
 }
 
+/// A reader, along with whether the card last handled on it still has to be
+/// removed before it reports cards again.
+struct ReaderSlot {
+    handle: NfcReaderHandle,
+    awaiting_removal: bool,
+    /// Card counted on the reader, whose re-scan window starts once removed.
+    counted_uid: Option<NfcUid>,
+    /// Wait for the next event, kept until it completes, as a card reported
+    /// by the backend is lost once the wait is dropped.
+    pending: Option<PendingEvent>,
+}
+
+impl ReaderSlot {
+    fn new(handle: NfcReaderHandle) -> Self {
+        Self {
+            handle,
+            awaiting_removal: false,
+            counted_uid: None,
+            pending: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum ReaderEvent {
+    Card(NfcUid),
+    Removed,
+}
+
+type PendingEvent = Pin<Box<dyn Future<Output = Result<ReaderEvent>> + Send + Sync>>;
+
+fn reader_event(reader: &NfcReader, awaiting_removal: bool) -> PendingEvent {
+    let reader = reader.clone();
+
+    if awaiting_removal {
+        Box::pin(async move {
+            reader.wait_for_removal().await?;
+            Ok(ReaderEvent::Removed)
+        })
+    } else {
+        Box::pin(async move { Ok(ReaderEvent::Card(reader.wait_for_card().await?)) })
+    }
+}
+
+/// Waits for the next event on any of the readers, returning the index of the
+/// reader along with the event.
+///
+/// Cancel safe, waits which did not complete are picked up again by the next
+/// call.
+async fn next_reader_event(readers: &mut [ReaderSlot]) -> (usize, Result<ReaderEvent>) {
+    poll_fn(|cx| {
+        for (index, slot) in readers.iter_mut().enumerate() {
+            let pending = slot
+                .pending
+                .get_or_insert_with(|| reader_event(&slot.handle.reader, slot.awaiting_removal));
+
+            if let Poll::Ready(result) = pending.as_mut().poll(cx) {
+                slot.pending = None;
+                return Poll::Ready((index, result));
+            }
+        }
+
+        Poll::Pending
+    })
+    .await
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 struct EngineState {
     audio_manifest_hash: Option<DataHash>,
@@ -383,3 +814,419 @@ impl IntoSubsystem<Error> for Engine {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recording::{Event, Recorder};
+    use async_trait::async_trait;
+    use bloop_client_framework::nfc::NfcReaderRequest;
+    use bloop_protocol::message::AchievementRecord;
+    use hex::FromHex;
+    use tempfile::TempDir;
+    use tokio::sync::broadcast;
+    use tokio_graceful_shutdown::{SubsystemBuilder, Toplevel};
+    use uuid::Uuid;
+
+    const CARD: &str = "04a1b2c3d4e5f6";
+    const CONFIG_CARD: &str = "04000000000001";
+
+    /// Answers every bloop with the same response.
+    struct ScriptedClient {
+        bloop_response: fn() -> Result<Vec<AchievementRecord>, RequestError>,
+    }
+
+    #[async_trait]
+    impl NetworkClient for ScriptedClient {
+        async fn configure(&self, _config: Option<ConnectionConfig>) -> Result<(), RequestError> {
+            Ok(())
+        }
+
+        async fn bloop(&self, _nfc_uid: NfcUid) -> Result<Vec<AchievementRecord>, RequestError> {
+            (self.bloop_response)()
+        }
+
+        async fn retrieve_audio(&self, _achievement_id: Uuid) -> Result<Vec<u8>, RequestError> {
+            Ok(b"ID3".to_vec())
+        }
+
+        async fn preload_check(
+            &self,
+            _audio_manifest_hash: Option<DataHash>,
+        ) -> Result<PreloadOutcome, RequestError> {
+            Ok(PreloadOutcome::Match)
+        }
+
+        async fn shutdown(&self) {}
+    }
+
+    /// Serves the requests of a reader from a card field, in which the test
+    /// places and removes cards.
+    fn card_field(
+        mut request_rx: mpsc::Receiver<NfcReaderRequest>,
+    ) -> watch::Sender<Option<NfcUid>> {
+        let (field_tx, field_rx) = watch::channel(None);
+
+        tokio::spawn(async move {
+            while let Some(request) = request_rx.recv().await {
+                let mut field_rx = field_rx.clone();
+
+                tokio::spawn(async move {
+                    match request {
+                        NfcReaderRequest::WaitForCard(response) => {
+                            if let Ok(card) = field_rx.wait_for(Option::is_some).await {
+                                let _ = response.send(card.unwrap());
+                            }
+                        }
+                        NfcReaderRequest::WaitForRemoval(response) => {
+                            if field_rx.wait_for(Option::is_none).await.is_ok() {
+                                let _ = response.send(());
+                            }
+                        }
+                        NfcReaderRequest::ReadNdefText(response) => {
+                            let _ = response.send(Err("no NDEF message".to_string()));
+                        }
+                    }
+                });
+            }
+        });
+
+        field_tx
+    }
+
+    /// An engine running against a recorder, a scripted network client and a
+    /// single reader.
+    struct Harness {
+        data_dir: TempDir,
+        recorder: Recorder,
+        palette: Palette,
+        field: watch::Sender<Option<NfcUid>>,
+        network_status: watch::Sender<NetworkStatus>,
+        _health_tx: mpsc::Sender<ReaderHealth>,
+        button_action_tx: broadcast::Sender<ButtonAction>,
+        _volume_rx: mpsc::Receiver<VolumeCommand>,
+        playback_rx: mpsc::Receiver<Playback>,
+    }
+
+    impl Harness {
+        async fn start(
+            config: &str,
+            bloop_response: fn() -> Result<Vec<AchievementRecord>, RequestError>,
+        ) -> Self {
+            Self::start_with(
+                Recorder::new(),
+                RescanConfig::default(),
+                config,
+                bloop_response,
+            )
+            .await
+        }
+
+        async fn start_with(
+            recorder: Recorder,
+            rescan_config: RescanConfig,
+            config: &str,
+            bloop_response: fn() -> Result<Vec<AchievementRecord>, RequestError>,
+        ) -> Self {
+            let data_dir = tempfile::tempdir().unwrap();
+            let (reader, request_rx, _) = NfcReaderHandle::channel("test");
+            let (health_tx, nfc_health_rx) = mpsc::channel(1);
+            let (network_status_tx, network_status) = watch::channel(NetworkStatus::Connected {
+                preload_check: false,
+            });
+            let (button_action_tx, button_action_rx) = broadcast::channel(1);
+            let (volume_tx, volume_rx) = mpsc::channel(1);
+            let (playback_tx, playback_rx) = mpsc::channel(1);
+
+            let mut engine = Engine::new(EngineProps {
+                config: toml::from_str(config).unwrap(),
+                data_path: data_dir.path().to_path_buf(),
+                led_controller: recorder.led_controller(),
+                nfc_readers: vec![reader],
+                nfc_health_rx,
+                network_client: Arc::new(ScriptedClient { bloop_response }),
+                audio_player: recorder.audio_player(data_dir.path()).await.unwrap(),
+                playback_tx,
+                network_status,
+                volume_tx,
+                audio_cache_config: AudioCacheConfig::default(),
+                palette: Palette::default(),
+                button_action_rx,
+                access_config: AccessConfig::default(),
+                rescan_config,
+            })
+            .await
+            .unwrap();
+            engine
+                .state
+                .mutate(|state| {
+                    state
+                        .config_nfc_uids
+                        .insert(NfcUid::from_hex(CONFIG_CARD).unwrap());
+                })
+                .unwrap();
+
+            tokio::spawn(
+                Toplevel::new(async |s: &mut SubsystemHandle| {
+                    s.start(SubsystemBuilder::new("Engine", engine.into_subsystem()));
+                })
+                .handle_shutdown_requests(Duration::from_secs(1)),
+            );
+
+            Self {
+                data_dir,
+                recorder,
+                palette: Palette::default(),
+                field: card_field(request_rx),
+                network_status: network_status_tx,
+                _health_tx: health_tx,
+                button_action_tx,
+                _volume_rx: volume_rx,
+                playback_rx,
+            }
+        }
+
+        /// Places the card on the reader and leaves it there for the given
+        /// time.
+        async fn present(&self, card: &str, duration: Duration) {
+            self.field
+                .send_replace(Some(NfcUid::from_hex(card).unwrap()));
+            sleep(duration).await;
+        }
+
+        fn led(&self, state: StatusLed) -> Event {
+            Event::Led(self.palette.animation(state))
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shows_rejected_bloop_before_returning_to_idle() {
+        let harness = Harness::start("", || {
+            Err(RequestError::Error(ErrorResponse::UnknownNfcUid))
+        })
+        .await;
+
+        harness.present(CARD, Duration::from_secs(1)).await;
+        let events = harness.recorder.events();
+        sleep(OUTCOME_DURATION).await;
+
+        assert_eq!(
+            events,
+            vec![
+                harness.led(StatusLed::IdleConnected),
+                harness.led(StatusLed::Busy),
+                Event::sound("bloops/bloop.mp3", AudioCategory::Bloop),
+                harness.led(StatusLed::BloopRejected),
+                Event::sound("error.mp3", AudioCategory::System),
+            ]
+        );
+        assert_eq!(
+            harness.recorder.events()[events.len()..],
+            [harness.led(StatusLed::IdleConnected)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn plays_awards_and_achievements_of_accepted_bloops() {
+        let harness = Harness::start("", || {
+            Ok(vec![AchievementRecord {
+                id: Uuid::from_u128(1),
+                audio_hash: Some(DataHash::from_hex("00112233445566778899aabbccddeeff").unwrap()),
+            }])
+        })
+        .await;
+
+        harness.present(CARD, OUTCOME_DURATION * 2).await;
+        let events = harness.recorder.events();
+
+        assert_eq!(
+            events[..4],
+            [
+                harness.led(StatusLed::IdleConnected),
+                harness.led(StatusLed::Busy),
+                Event::sound("bloops/bloop.mp3", AudioCategory::Bloop),
+                harness.led(StatusLed::BloopAccepted),
+            ]
+        );
+        assert_eq!(
+            events[4..6],
+            [
+                harness.led(StatusLed::Achievement),
+                Event::sound("awards/award.mp3", AudioCategory::Award),
+            ]
+        );
+        assert!(matches!(
+            &events[6],
+            Event::Sound { path, category: AudioCategory::Achievement }
+                if path.starts_with(harness.data_dir.path().join("cache"))
+        ));
+        assert_eq!(events[7..], [harness.led(StatusLed::IdleConnected)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn queues_achievements_in_fast_lane() {
+        let mut harness = Harness::start("fast_lane = true", || {
+            Ok(vec![AchievementRecord {
+                id: Uuid::from_u128(2),
+                audio_hash: Some(DataHash::from_hex("ffeeddccbbaa99887766554433221100").unwrap()),
+            }])
+        })
+        .await;
+
+        harness.present(CARD, Duration::from_secs(1)).await;
+
+        assert_eq!(
+            harness.recorder.events(),
+            vec![
+                harness.led(StatusLed::IdleConnected),
+                harness.led(StatusLed::Busy),
+                Event::sound("bloops/bloop.mp3", AudioCategory::Bloop),
+                harness.led(StatusLed::BloopAccepted),
+            ]
+        );
+        assert_eq!(harness.playback_rx.try_recv().unwrap().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ignores_cards_while_offline() {
+        let harness = Harness::start("", || panic!("bloop sent while offline")).await;
+        sleep(Duration::from_millis(100)).await;
+        harness
+            .network_status
+            .send_replace(NetworkStatus::Disconnected);
+
+        harness.present(CARD, OUTCOME_DURATION * 2).await;
+
+        assert_eq!(
+            harness.recorder.events(),
+            vec![
+                harness.led(StatusLed::IdleConnected),
+                harness.led(StatusLed::Offline),
+                harness.led(StatusLed::Busy),
+                harness.led(StatusLed::Offline),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shows_missing_audio_output_in_every_idle_state() {
+        let recorder = Recorder::new();
+        recorder.remove_output();
+        let harness =
+            Harness::start_with(recorder, RescanConfig::default(), "", || Ok(vec![])).await;
+        sleep(Duration::from_millis(100)).await;
+        harness
+            .network_status
+            .send_replace(NetworkStatus::Disconnected);
+        sleep(Duration::from_millis(100)).await;
+        harness
+            .network_status
+            .send_replace(NetworkStatus::Unconfigured);
+        sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(
+            harness.recorder.events(),
+            vec![harness.led(StatusLed::AudioUnavailable); 3]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn cues_cards_presented_again() {
+        let harness = Harness::start("", || Ok(vec![])).await;
+
+        harness.present(CARD, OUTCOME_DURATION * 2).await;
+        harness.field.send_replace(None);
+        sleep(Duration::from_millis(100)).await;
+        let events = harness.recorder.events();
+        harness.present(CARD, Duration::from_millis(100)).await;
+
+        assert_eq!(
+            harness.recorder.events()[events.len()..],
+            [
+                harness.led(StatusLed::Busy),
+                harness.led(StatusLed::BloopAccepted),
+                Event::sound("already-counted.mp3", AudioCategory::System),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn returns_to_idle_right_away_for_ignored_cards() {
+        let rescan_config = toml::from_str(r#"response = "ignore""#).unwrap();
+        let harness = Harness::start_with(Recorder::new(), rescan_config, "", || Ok(vec![])).await;
+
+        harness.present(CARD, OUTCOME_DURATION * 2).await;
+        harness.field.send_replace(None);
+        sleep(Duration::from_millis(100)).await;
+        let events = harness.recorder.events();
+        harness.present(CARD, Duration::from_millis(100)).await;
+
+        assert_eq!(
+            harness.recorder.events()[events.len()..],
+            [
+                harness.led(StatusLed::Busy),
+                harness.led(StatusLed::IdleConnected),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_config_cards_without_command() {
+        let harness = Harness::start("", || panic!("config card sent as bloop")).await;
+
+        harness.present(CONFIG_CARD, Duration::from_secs(1)).await;
+        harness.field.send_replace(None);
+        sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(
+            harness.recorder.events(),
+            vec![
+                harness.led(StatusLed::IdleConnected),
+                harness.led(StatusLed::Busy),
+                harness.led(StatusLed::ConfigError),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flashes_volume_changes_over_the_current_state() {
+        let harness = Harness::start("", || panic!("no card presented")).await;
+        sleep(Duration::from_millis(100)).await;
+
+        harness
+            .button_action_tx
+            .send(ButtonAction::VolumeUp)
+            .unwrap();
+        sleep(Duration::from_secs(1)).await;
+
+        assert_eq!(
+            harness.recorder.events(),
+            vec![
+                harness.led(StatusLed::IdleConnected),
+                harness.led(StatusLed::VolumeChange),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_reader_waits_across_cancellation() {
+        let (handle, mut request_rx, _) = NfcReaderHandle::channel("test");
+        let mut readers = vec![ReaderSlot::new(handle)];
+        let card = NfcUid::from_hex(CARD).unwrap();
+
+        select! {
+            _ = next_reader_event(&mut readers) => panic!("no card presented"),
+            _ = sleep(Duration::from_millis(10)) => {}
+        }
+
+        let Some(NfcReaderRequest::WaitForCard(response)) = request_rx.recv().await else {
+            panic!("reader was not asked for a card");
+        };
+        response.send(card).unwrap();
+
+        let (index, event) = next_reader_event(&mut readers).await;
+        assert_eq!(index, 0);
+        assert_eq!(event.unwrap(), ReaderEvent::Card(card));
+        assert!(request_rx.try_recv().is_err());
+    }
+}
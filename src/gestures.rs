@@ -0,0 +1,599 @@
+//! Recognition of button gestures.
+//!
+//! Turns the raw press and release events of the buttons into the actions
+//! mapped to their gestures. Actions reported directly by the hardware are
+//! passed on as they are. Actions are broadcast, every consumer picks the
+//! ones it handles.
+
+use crate::hardware::buttons::{Button, ButtonReceiver};
+use anyhow::{Error, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::select;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{sleep_until, Instant};
+use tokio_graceful_shutdown::{FutureExt, IntoSubsystem, SubsystemHandle};
+use tracing::{debug, info};
+
+/// Actions which can be bound to gestures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ButtonAction {
+    VolumeUp,
+    VolumeDown,
+    ToggleMute,
+    /// Replays the achievement audio of the last bloop.
+    Replay,
+    StatusReadout,
+    Shutdown,
+}
+
+impl ButtonAction {
+    /// Whether the action is repeated while its button is held.
+    fn is_repeatable(&self) -> bool {
+        matches!(self, Self::VolumeUp | Self::VolumeDown)
+    }
+
+    /// Whether the action is handled by the volume control.
+    pub fn changes_volume(&self) -> bool {
+        matches!(self, Self::VolumeUp | Self::VolumeDown | Self::ToggleMute)
+    }
+}
+
+pub type ButtonActionSender = broadcast::Sender<ButtonAction>;
+pub type ButtonActionReceiver = broadcast::Receiver<ButtonAction>;
+
+/// Actions of the gestures of a single button.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ButtonMapping {
+    press: Option<ButtonAction>,
+    long_press: Option<ButtonAction>,
+    double_press: Option<ButtonAction>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GestureConfig {
+    /// Time a button has to be held for a long press.
+    long_press_ms: u64,
+    /// Maximum time between the release of the first press and the second
+    /// press of a double press.
+    double_press_ms: u64,
+    /// Time the combo buttons have to be held together.
+    combo_ms: u64,
+    /// Whether volume presses repeat while the button is held.
+    repeat: bool,
+    /// Time a button has to be held before the first repetition.
+    repeat_delay_ms: u64,
+    /// Time between the first repetitions, which shrinks with every
+    /// repetition down to `repeat_min_interval_ms`.
+    repeat_interval_ms: u64,
+    repeat_min_interval_ms: u64,
+    /// Mappings by button name. The volume and mute buttons are mapped to the
+    /// matching actions unless configured otherwise.
+    buttons: HashMap<Button, ButtonMapping>,
+    combo_buttons: Vec<Button>,
+    /// Action triggered by holding all combo buttons. Disabled by default, as
+    /// combo buttons only react on release.
+    combo: Option<ButtonAction>,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            long_press_ms: 1000,
+            double_press_ms: 300,
+            combo_ms: 10_000,
+            repeat: true,
+            repeat_delay_ms: 500,
+            repeat_interval_ms: 200,
+            repeat_min_interval_ms: 50,
+            buttons: HashMap::new(),
+            combo_buttons: vec![
+                Button::new(Button::VOLUME_UP),
+                Button::new(Button::VOLUME_DOWN),
+            ],
+            combo: None,
+        }
+    }
+}
+
+impl GestureConfig {
+    fn mapping(&self, button: &Button) -> ButtonMapping {
+        if let Some(mapping) = self.buttons.get(button) {
+            return mapping.clone();
+        }
+
+        let press = match button.name() {
+            Button::VOLUME_UP => Some(ButtonAction::VolumeUp),
+            Button::VOLUME_DOWN => Some(ButtonAction::VolumeDown),
+            Button::MUTE => Some(ButtonAction::ToggleMute),
+            _ => None,
+        };
+
+        ButtonMapping {
+            press,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the press action of the button is repeated while it is held.
+    /// Buttons with a long press never repeat.
+    fn repeats(&self, mapping: &ButtonMapping) -> bool {
+        self.repeat
+            && mapping.long_press.is_none()
+            && mapping.press.is_some_and(|action| action.is_repeatable())
+    }
+
+    fn is_combo_button(&self, button: &Button) -> bool {
+        self.combo.is_some() && self.combo_buttons.contains(button)
+    }
+
+    /// Whether a press of the button can be handled as soon as it happens,
+    /// as no gesture could start with it.
+    fn is_immediate(&self, button: &Button) -> bool {
+        let mapping = self.mapping(button);
+
+        !self.is_combo_button(button)
+            && mapping.long_press.is_none()
+            && mapping.double_press.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PressState {
+    Idle,
+    /// Pressed at the given time, no gesture recognized yet.
+    Held(Instant),
+    /// Released after a short press, waiting for a second press until the
+    /// given time.
+    Released(Instant),
+    /// Still pressed and repeating the press action, next at the given time
+    /// and then after the given interval.
+    Repeating(Instant, Duration),
+    /// Still pressed, but the press was already handled.
+    Handled,
+}
+
+/// Factor applied to the repeat interval after every repetition.
+const REPEAT_ACCELERATION: f32 = 0.8;
+
+pub struct GestureTask {
+    button_rx: ButtonReceiver,
+    direct_action_rx: mpsc::Receiver<ButtonAction>,
+    action_tx: ButtonActionSender,
+    config: GestureConfig,
+    states: HashMap<Button, PressState>,
+    combo_started: Option<Instant>,
+}
+
+impl GestureTask {
+    pub fn new(
+        button_rx: ButtonReceiver,
+        direct_action_rx: mpsc::Receiver<ButtonAction>,
+        action_tx: ButtonActionSender,
+        config: GestureConfig,
+    ) -> Self {
+        Self {
+            button_rx,
+            direct_action_rx,
+            action_tx,
+            config,
+            states: HashMap::new(),
+            combo_started: None,
+        }
+    }
+
+    async fn listen(&mut self) -> Result<()> {
+        loop {
+            let deadline = self.next_deadline();
+
+            select! {
+                event = self.button_rx.recv() => {
+                    let Some(event) = event else {
+                        break;
+                    };
+
+                    if event.pressed {
+                        self.handle_press(event.button);
+                    } else {
+                        self.handle_release(event.button);
+                    }
+                }
+                Some(action) = self.direct_action_rx.recv() => {
+                    self.trigger(Some(action));
+                }
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    self.handle_deadlines();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_press(&mut self, button: Button) {
+        let now = Instant::now();
+        let mapping = self.config.mapping(&button);
+
+        match self.state(&button) {
+            PressState::Released(_) => {
+                self.set_state(&button, PressState::Handled);
+                info!("double press on {}", button);
+                self.trigger(mapping.double_press);
+            }
+            _ if self.config.is_immediate(&button) => {
+                let state = if self.config.repeats(&mapping) {
+                    PressState::Repeating(
+                        now + Duration::from_millis(self.config.repeat_delay_ms),
+                        Duration::from_millis(self.config.repeat_interval_ms),
+                    )
+                } else {
+                    PressState::Handled
+                };
+
+                self.set_state(&button, state);
+                self.trigger(mapping.press);
+            }
+            _ => self.set_state(&button, PressState::Held(now)),
+        }
+
+        if self.config.is_combo_button(&button) && self.is_combo_held() {
+            // Buttons taking part in the combo do not trigger actions of
+            // their own.
+            for button in &self.config.combo_buttons {
+                self.states.insert(button.clone(), PressState::Handled);
+            }
+
+            self.combo_started = Some(now);
+        }
+    }
+
+    fn handle_release(&mut self, button: Button) {
+        if self.config.is_combo_button(&button) {
+            self.combo_started = None;
+        }
+
+        let mapping = self.config.mapping(&button);
+
+        match self.state(&button) {
+            PressState::Held(_) if mapping.double_press.is_some() => {
+                let deadline = Instant::now() + Duration::from_millis(self.config.double_press_ms);
+                self.set_state(&button, PressState::Released(deadline));
+            }
+            PressState::Held(_) => {
+                self.set_state(&button, PressState::Idle);
+                self.trigger(mapping.press);
+            }
+            PressState::Released(_) => {}
+            PressState::Idle | PressState::Repeating(..) | PressState::Handled => {
+                self.set_state(&button, PressState::Idle)
+            }
+        }
+    }
+
+    fn handle_deadlines(&mut self) {
+        let now = Instant::now();
+        let long_press = Duration::from_millis(self.config.long_press_ms);
+        let repeat_delay = Duration::from_millis(self.config.repeat_delay_ms);
+        let min_interval = Duration::from_millis(self.config.repeat_min_interval_ms);
+        let mut actions = Vec::new();
+
+        for (button, state) in self.states.iter_mut() {
+            let mapping = self.config.mapping(button);
+
+            match *state {
+                PressState::Held(since)
+                    if mapping.long_press.is_some() && since + long_press <= now =>
+                {
+                    info!("long press on {}", button);
+                    actions.extend(mapping.long_press);
+                    *state = PressState::Handled;
+                }
+                PressState::Held(since)
+                    if since + repeat_delay <= now && self.config.repeats(&mapping) =>
+                {
+                    let interval = Duration::from_millis(self.config.repeat_interval_ms);
+                    actions.extend(mapping.press);
+                    *state = PressState::Repeating(now + interval, interval);
+                }
+                PressState::Repeating(next, interval) if next <= now => {
+                    let interval = interval.mul_f32(REPEAT_ACCELERATION).max(min_interval);
+                    actions.extend(mapping.press);
+                    *state = PressState::Repeating(now + interval, interval);
+                }
+                PressState::Released(deadline) if deadline <= now => {
+                    actions.extend(mapping.press);
+                    *state = PressState::Idle;
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(started) = self.combo_started {
+            if started + Duration::from_millis(self.config.combo_ms) <= now {
+                self.combo_started = None;
+                info!("button combo held");
+                actions.extend(self.config.combo);
+            }
+        }
+
+        for action in actions {
+            self.trigger(Some(action));
+        }
+    }
+
+    fn trigger(&self, action: Option<ButtonAction>) {
+        let Some(action) = action else {
+            return;
+        };
+
+        debug!("button action {:?}", action);
+
+        // Sending only fails while nobody is subscribed.
+        let _ = self.action_tx.send(action);
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        let long_press = Duration::from_millis(self.config.long_press_ms);
+        let repeat_delay = Duration::from_millis(self.config.repeat_delay_ms);
+        let button_deadlines = self.states.iter().filter_map(|(button, state)| {
+            let mapping = self.config.mapping(button);
+
+            match *state {
+                PressState::Held(since) if mapping.long_press.is_some() => Some(since + long_press),
+                PressState::Held(since) if self.config.repeats(&mapping) => {
+                    Some(since + repeat_delay)
+                }
+                PressState::Released(deadline) | PressState::Repeating(deadline, _) => {
+                    Some(deadline)
+                }
+                _ => None,
+            }
+        });
+        let combo_deadline = self
+            .combo_started
+            .map(|started| started + Duration::from_millis(self.config.combo_ms));
+
+        button_deadlines.chain(combo_deadline).min()
+    }
+
+    fn state(&self, button: &Button) -> PressState {
+        self.states.get(button).copied().unwrap_or(PressState::Idle)
+    }
+
+    fn set_state(&mut self, button: &Button, state: PressState) {
+        self.states.insert(button.clone(), state);
+    }
+
+    fn is_combo_held(&self) -> bool {
+        self.config.combo_buttons.iter().all(|button| {
+            matches!(
+                self.state(button),
+                PressState::Held(_) | PressState::Repeating(..) | PressState::Handled
+            )
+        })
+    }
+}
+
+impl IntoSubsystem<Error> for GestureTask {
+    async fn run(mut self, subsys: &mut SubsystemHandle) -> Result<()> {
+        if let Ok(result) = self.listen().cancel_on_shutdown(subsys).await {
+            result?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::buttons::ButtonEvent;
+    use tokio::sync::mpsc;
+    use tokio::time::sleep;
+
+    struct Buttons {
+        event_tx: mpsc::Sender<ButtonEvent>,
+        direct_action_tx: mpsc::Sender<ButtonAction>,
+        action_rx: ButtonActionReceiver,
+    }
+
+    impl Buttons {
+        fn spawn(config: GestureConfig) -> Self {
+            let (event_tx, event_rx) = mpsc::channel(8);
+            let (direct_action_tx, direct_action_rx) = mpsc::channel(8);
+            let (action_tx, action_rx) = broadcast::channel(16);
+            let mut task = GestureTask::new(event_rx, direct_action_rx, action_tx, config);
+            tokio::spawn(async move { task.listen().await });
+
+            Self {
+                event_tx,
+                direct_action_tx,
+                action_rx,
+            }
+        }
+
+        async fn send(&self, name: &str, pressed: bool) {
+            let button = Button::new(name);
+            self.event_tx
+                .send(ButtonEvent { button, pressed })
+                .await
+                .unwrap();
+        }
+
+        /// Holds a button for the given time, returning the actions
+        /// triggered until it was released.
+        async fn hold(&mut self, name: &str, ms: u64) -> Vec<ButtonAction> {
+            self.send(name, true).await;
+            sleep(Duration::from_millis(ms)).await;
+            self.send(name, false).await;
+            self.wait(0).await
+        }
+
+        /// Waits for the given time, returning the actions triggered so far.
+        async fn wait(&mut self, ms: u64) -> Vec<ButtonAction> {
+            sleep(Duration::from_millis(ms)).await;
+            tokio::task::yield_now().await;
+
+            let mut actions = Vec::new();
+
+            while let Ok(action) = self.action_rx.try_recv() {
+                actions.push(action);
+            }
+
+            actions
+        }
+    }
+
+    fn mapping(
+        press: Option<ButtonAction>,
+        long_press: Option<ButtonAction>,
+        double_press: Option<ButtonAction>,
+    ) -> ButtonMapping {
+        ButtonMapping {
+            press,
+            long_press,
+            double_press,
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn volume_buttons_react_on_press() {
+        let mut buttons = Buttons::spawn(GestureConfig::default());
+
+        buttons.send(Button::VOLUME_UP, true).await;
+        assert_eq!(buttons.wait(10).await, [ButtonAction::VolumeUp]);
+        buttons.send(Button::VOLUME_UP, false).await;
+        assert_eq!(buttons.wait(1000).await, []);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn repeats_held_volume_buttons() {
+        let mut buttons = Buttons::spawn(GestureConfig::default());
+
+        // Pressed at 0ms, repeated at 500ms and 660ms.
+        assert_eq!(
+            buttons.hold(Button::VOLUME_DOWN, 700).await,
+            [ButtonAction::VolumeDown; 3]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn repeats_with_delay_beyond_long_press() {
+        // Combo buttons wait for the repeat delay before the first action.
+        let mut buttons = Buttons::spawn(GestureConfig {
+            long_press_ms: 1000,
+            repeat_delay_ms: 1500,
+            combo: Some(ButtonAction::StatusReadout),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            buttons.hold(Button::VOLUME_UP, 1200).await,
+            [ButtonAction::VolumeUp]
+        );
+        assert_eq!(
+            buttons.hold(Button::VOLUME_UP, 1600).await,
+            [ButtonAction::VolumeUp]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recognizes_long_press() {
+        let mut buttons = Buttons::spawn(GestureConfig {
+            buttons: HashMap::from([(
+                Button::new("replay"),
+                mapping(
+                    Some(ButtonAction::Replay),
+                    Some(ButtonAction::StatusReadout),
+                    None,
+                ),
+            )]),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            buttons.hold("replay", 1200).await,
+            [ButtonAction::StatusReadout]
+        );
+        assert_eq!(buttons.hold("replay", 200).await, [ButtonAction::Replay]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recognizes_double_press() {
+        let mut buttons = Buttons::spawn(GestureConfig {
+            buttons: HashMap::from([(
+                Button::new("replay"),
+                mapping(
+                    Some(ButtonAction::Replay),
+                    None,
+                    Some(ButtonAction::StatusReadout),
+                ),
+            )]),
+            ..Default::default()
+        });
+
+        assert_eq!(buttons.hold("replay", 100).await, []);
+        assert_eq!(
+            buttons.hold("replay", 100).await,
+            [ButtonAction::StatusReadout]
+        );
+        assert_eq!(buttons.wait(1000).await, []);
+
+        assert_eq!(buttons.hold("replay", 100).await, []);
+        assert_eq!(buttons.wait(400).await, [ButtonAction::Replay]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn recognizes_combo() {
+        let mut buttons = Buttons::spawn(GestureConfig {
+            combo: Some(ButtonAction::StatusReadout),
+            ..Default::default()
+        });
+
+        buttons.send(Button::VOLUME_UP, true).await;
+        buttons.send(Button::VOLUME_DOWN, true).await;
+        assert_eq!(buttons.wait(10_000).await, [ButtonAction::StatusReadout]);
+        buttons.send(Button::VOLUME_UP, false).await;
+        buttons.send(Button::VOLUME_DOWN, false).await;
+        assert_eq!(buttons.wait(1000).await, []);
+
+        // A single combo button still changes the volume on release.
+        assert_eq!(
+            buttons.hold(Button::VOLUME_UP, 100).await,
+            [ButtonAction::VolumeUp]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn passes_direct_actions_on_as_they_are() {
+        let mut buttons = Buttons::spawn(GestureConfig {
+            buttons: HashMap::from([(
+                Button::new(Button::VOLUME_UP),
+                mapping(
+                    Some(ButtonAction::Replay),
+                    None,
+                    Some(ButtonAction::StatusReadout),
+                ),
+            )]),
+            ..Default::default()
+        });
+
+        for _ in 0..2 {
+            buttons
+                .direct_action_tx
+                .send(ButtonAction::VolumeUp)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            buttons.wait(10).await,
+            [ButtonAction::VolumeUp, ButtonAction::VolumeUp]
+        );
+        assert_eq!(buttons.wait(1000).await, []);
+    }
+}
@@ -1,9 +1,37 @@
+use serde::Deserialize;
+use std::fmt;
 use tokio::sync::mpsc;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Button {
-    VolumeUp,
-    VolumeDown,
+/// Name of a button, e.g. `volume-up`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(transparent)]
+pub struct Button(String);
+
+impl Button {
+    pub const VOLUME_UP: &'static str = "volume-up";
+    pub const VOLUME_DOWN: &'static str = "volume-down";
+    pub const MUTE: &'static str = "mute";
+
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A button being pressed or released.
+#[derive(Debug, Clone)]
+pub struct ButtonEvent {
+    pub button: Button,
+    pub pressed: bool,
 }
 
-pub type ButtonReceiver = mpsc::Receiver<Button>;
+pub type ButtonReceiver = mpsc::Receiver<ButtonEvent>;
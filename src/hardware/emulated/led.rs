@@ -1,45 +1,51 @@
-use crate::hardware::led::LedState;
+use crate::hardware::led::{scale, AnimationPlayer, BrightnessConfig, DefaultBrightness, LedState};
 use anyhow::{Error, Result};
 use eframe::epaint::Color32;
 use std::time::Duration;
 use tokio::select;
 use tokio::sync::{mpsc, watch};
-use tokio::time::{interval, Instant};
+use tokio::time::interval;
 use tokio_graceful_shutdown::{FutureExt, IntoSubsystem, SubsystemHandle};
 
-const BREATHING_PERIOD: Duration = Duration::from_secs(4);
-
 #[derive(Debug)]
 pub struct LedControllerTask {
     rx: mpsc::Receiver<LedState>,
     tx: watch::Sender<Color32>,
-    state: Option<LedState>,
-    breathing_start: Instant,
+    player: AnimationPlayer,
+    brightness: BrightnessConfig,
 }
 
 impl LedControllerTask {
-    pub fn new(rx: mpsc::Receiver<LedState>, tx: watch::Sender<Color32>) -> Self {
+    pub fn new(
+        rx: mpsc::Receiver<LedState>,
+        tx: watch::Sender<Color32>,
+        brightness: BrightnessConfig,
+    ) -> Self {
         Self {
             rx,
             tx,
-            state: None,
-            breathing_start: Instant::now(),
+            player: AnimationPlayer::new(),
+            brightness,
         }
     }
 
     async fn process(&mut self) -> Result<()> {
         let mut ticker = interval(Duration::from_millis(60));
+        let mut brightness_ticker = interval(Duration::from_secs(1));
 
         loop {
+            let is_steady = self.player.is_steady();
+
             select! {
                 state = self.rx.recv() => match state {
                     Some(state) => {
-                        self.state = Some(state);
-                        self.breathing_start = Instant::now();
+                        self.player.set(state);
+                        self.handle_tick().await?;
                     }
                     None => break,
                 },
-                _ = ticker.tick() => self.handle_tick().await?,
+                _ = ticker.tick(), if !is_steady => self.handle_tick().await?,
+                _ = brightness_ticker.tick() => self.handle_tick().await?,
             }
         }
 
@@ -47,31 +53,13 @@ impl LedControllerTask {
     }
 
     async fn handle_tick(&mut self) -> Result<()> {
-        let Some(state) = self.state.as_ref() else {
-            return Ok(());
-        };
-
-        let new_color = match state {
-            LedState::Off => Color32::BLACK,
-            LedState::Static(color) => {
-                let (r, g, b) = color.rgb();
-                Color32::from_rgb(r, g, b)
-            }
-            LedState::Breathing(color) => {
-                let (r, g, b) = color.rgb();
-
-                let elapsed = self.breathing_start.elapsed().as_secs_f32();
-                let t = (elapsed % BREATHING_PERIOD.as_secs_f32()) / BREATHING_PERIOD.as_secs_f32();
-                let brightness = 0.5 * (1.0 - (2.0 * std::f32::consts::PI * t).cos());
-
-                let (r, g, b) = (
-                    (r as f32 * brightness) as u8,
-                    (g as f32 * brightness) as u8,
-                    (b as f32 * brightness) as u8,
-                );
-                Color32::from_rgb(r, g, b)
-            }
-        };
+        // The emulated LED has no glare to tame, so it uses the defaults of
+        // drivers without brightness defaults of their own.
+        let brightness = self
+            .brightness
+            .current(self.player.current(), DefaultBrightness::FULL);
+        let (r, g, b) = scale(self.player.color(), brightness);
+        let new_color = Color32::from_rgb(r, g, b);
 
         if *self.tx.borrow() != new_color {
             let _ = self.tx.send(new_color);
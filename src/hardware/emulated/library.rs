@@ -0,0 +1,113 @@
+//! Cards saved in the emulator, so they can be presented again in later
+//! sessions without entering their UID and data each time.
+
+use crate::hardware::emulated::ui::EmulatedCard;
+use anyhow::{Context, Result};
+use rand::RngExt;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Length of generated UIDs, which are double size to not be mistaken for
+/// random UIDs.
+const RANDOM_UID_LEN: usize = 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedCard {
+    pub name: String,
+    #[serde(flatten)]
+    pub card: EmulatedCard,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CardLibrary {
+    #[serde(default, rename = "card")]
+    pub cards: Vec<SavedCard>,
+}
+
+impl CardLibrary {
+    /// Loads the library from a file, which is empty if the file does not
+    /// exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw_toml = match fs::read_to_string(path) {
+            Ok(raw_toml) => raw_toml,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => {
+                return Err(error).with_context(|| format!("failed to read {}", path.display()))
+            }
+        };
+
+        toml::from_str(&raw_toml).with_context(|| format!("failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {}", parent.display()))?;
+        }
+
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", tmp_path.display()))?;
+        fs::rename(&tmp_path, path)
+            .with_context(|| format!("failed to rename {}", tmp_path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SavedCard> {
+        self.cards.iter().find(|saved| saved.name == name)
+    }
+
+    /// Adds a card, replacing the one with the same name.
+    pub fn insert(&mut self, card: SavedCard) {
+        match self.cards.iter_mut().find(|saved| saved.name == card.name) {
+            Some(saved) => *saved = card,
+            None => self.cards.push(card),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.cards.retain(|saved| saved.name != name);
+    }
+
+    /// Adds all cards of another library, returning how many there were.
+    pub fn merge(&mut self, other: CardLibrary) -> usize {
+        let count = other.cards.len();
+
+        for card in other.cards {
+            self.insert(card);
+        }
+
+        count
+    }
+}
+
+/// Generates a UID in the format of the UID input, e.g. for load testing with
+/// many different participants.
+pub fn random_uid() -> String {
+    let bytes: [u8; RANDOM_UID_LEN] = rand::rng().random();
+
+    format_uid(&bytes)
+}
+
+/// Formats a UID for the UID input.
+pub fn format_uid(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_uids() {
+        assert_eq!(format_uid(&[0xaa, 0x0b, 0xcc]), "AA:0B:CC");
+        assert_eq!(random_uid().len(), RANDOM_UID_LEN * 3 - 1);
+    }
+}
@@ -1,12 +1,16 @@
+use crate::config::load_config;
+use crate::hardware::buttons::Button;
 use crate::hardware::emulated::led::LedControllerTask;
 use crate::hardware::emulated::nfc::NfcReaderTask;
 use crate::hardware::emulated::ui::{run_ui, UiChannels};
-use crate::hardware::led::LedController;
-use crate::hardware::nfc::NfcReader;
-use crate::hardware::{InitSubsystems, Peripherals, StartSubsystems};
+use crate::hardware::led::{BrightnessConfig, LedController};
+use crate::hardware::nfc::NfcReaderHandle;
+use crate::hardware::{data_dir, InitSubsystems, Peripherals, StartSubsystems};
 use crate::thread::SupervisedThread;
 use anyhow::Result;
 use egui::Color32;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::panic::AssertUnwindSafe;
 use tokio::sync::{mpsc, watch};
 use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, SubsystemHandle};
@@ -14,6 +18,7 @@ use tokio_util::sync::CancellationToken;
 
 pub mod asset;
 mod led;
+mod library;
 mod nfc;
 pub mod system;
 mod ui;
@@ -28,28 +33,44 @@ pub struct HardwareContext {
 pub fn init_hardware(shutdown_token: CancellationToken) -> Result<HardwareContext> {
     let (led_state_tx, led_state_rx) = mpsc::channel(32);
     let (button_tx, button_rx) = mpsc::channel(32);
-    let (nfc_reader_handle, nfc_reader_rx) = NfcReader::channel();
+    let (nfc_reader_handle, nfc_reader_rx, nfc_write_rx) = NfcReaderHandle::channel("emulated");
     let (led_ui_tx, led_ui_rx) = watch::channel(Color32::BLACK);
     let (emulated_card_tx, emulated_card_rx) = watch::channel(None);
+    let (written_card_tx, written_card_rx) = watch::channel(None);
 
     let ui_channels = UiChannels {
         button_tx,
         led_color_rx: led_ui_rx,
         emulated_card_tx,
+        written_card_rx,
     };
 
     let peripherals = Peripherals {
         led_controller: LedController::new(led_state_tx),
-        nfc_reader: nfc_reader_handle,
+        nfc_readers: vec![nfc_reader_handle],
+        // The emulated reader is always healthy.
+        nfc_health_rx: mpsc::channel(1).1,
         button_receiver: button_rx,
+        // The emulator has no encoder.
+        action_receiver: mpsc::channel(1).1,
     };
 
+    let config: Config = load_config()?;
+    let buttons = config.buttons.buttons();
+    let library_path = data_dir()?.join("cards.toml");
     let led_ui_tx = AssertUnwindSafe(led_ui_tx);
     let emulated_card_rx = AssertUnwindSafe(emulated_card_rx);
+    let written_card_tx = AssertUnwindSafe(written_card_tx);
 
     let init_subsystems = Box::new(move || -> Result<StartSubsystems> {
-        let led_controller = LedControllerTask::new(led_state_rx, led_ui_tx.clone());
-        let nfc_reader = NfcReaderTask::new(nfc_reader_rx, emulated_card_rx.clone());
+        let led_controller =
+            LedControllerTask::new(led_state_rx, led_ui_tx.clone(), config.led_controller);
+        let nfc_reader = NfcReaderTask::new(
+            nfc_reader_rx,
+            nfc_write_rx,
+            emulated_card_rx.clone(),
+            written_card_tx.clone(),
+        );
 
         Ok(Box::new(move |s: &SubsystemHandle| {
             s.start(SubsystemBuilder::new(
@@ -67,6 +88,43 @@ pub fn init_hardware(shutdown_token: CancellationToken) -> Result<HardwareContex
         peripherals,
         threads: vec![],
         init_subsystems,
-        run_ui: Box::new(move || run_ui(shutdown_token, ui_channels)),
+        run_ui: Box::new(move || run_ui(shutdown_token, ui_channels, buttons, library_path)),
     })
 }
+
+#[derive(Debug, Deserialize, Default)]
+struct Config {
+    #[serde(default)]
+    led_controller: BrightnessConfig,
+    #[serde(default)]
+    buttons: ButtonsConfig,
+}
+
+/// Reads the buttons of the Pi config, so the emulator offers the same ones.
+#[derive(Debug, Deserialize, Default)]
+struct ButtonsConfig {
+    #[serde(default)]
+    lines: Option<HashMap<Button, u32>>,
+}
+
+impl ButtonsConfig {
+    fn buttons(&self) -> Vec<Button> {
+        let mut buttons = vec![
+            Button::new(Button::VOLUME_DOWN),
+            Button::new(Button::VOLUME_UP),
+        ];
+
+        if let Some(lines) = &self.lines {
+            buttons.retain(|button| lines.contains_key(button));
+            let mut extra = lines
+                .keys()
+                .filter(|button| !buttons.contains(button))
+                .cloned()
+                .collect::<Vec<_>>();
+            extra.sort();
+            buttons.extend(extra);
+        }
+
+        buttons
+    }
+}
@@ -1,5 +1,6 @@
 use crate::hardware::emulated::ui::EmulatedCard;
-use crate::hardware::nfc::NfcReaderRequest;
+use crate::hardware::nfc::{NfcReaderRequest, NfcUid, NfcWriteRequest};
+use crate::ndef::{self, ConfigRecord};
 use anyhow::{Error, Result};
 use tokio::select;
 use tokio::sync::{mpsc, watch};
@@ -8,67 +9,100 @@ use tokio_graceful_shutdown::{FutureExt, IntoSubsystem, SubsystemHandle};
 #[derive(Debug)]
 pub struct NfcReaderTask {
     request_rx: mpsc::Receiver<NfcReaderRequest>,
+    write_rx: mpsc::Receiver<NfcWriteRequest>,
     ui_rx: watch::Receiver<Option<EmulatedCard>>,
+    /// Hands records written to the card to the UI, which updates the card.
+    written_tx: watch::Sender<Option<ConfigRecord>>,
+    /// UID of the card last handed to the engine.
+    reported_uid: Option<NfcUid>,
 }
 
 impl NfcReaderTask {
     pub fn new(
         cmd_rx: mpsc::Receiver<NfcReaderRequest>,
+        write_rx: mpsc::Receiver<NfcWriteRequest>,
         ui_rx: watch::Receiver<Option<EmulatedCard>>,
+        written_tx: watch::Sender<Option<ConfigRecord>>,
     ) -> Self {
         Self {
             request_rx: cmd_rx,
+            write_rx,
             ui_rx,
+            written_tx,
+            reported_uid: None,
         }
     }
 
     async fn process(&mut self) -> Result<()> {
-        while let Some(request) = self.request_rx.recv().await {
-            match request {
-                NfcReaderRequest::WaitForCard(mut response) => loop {
-                    if let Some(card) = self.ui_rx.borrow().clone() {
-                        let _ = response.send(card.uid);
-                        break;
-                    }
+        loop {
+            select! {
+                Some(request) = self.request_rx.recv() => self.handle_request(request).await,
+                Some(request) = self.write_rx.recv() => self.handle_write(request),
+                else => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_write(&mut self, request: NfcWriteRequest) {
+        let result = match self.ui_rx.borrow().as_ref() {
+            Some(card) if card.data.is_empty() => {
+                let _ = self.written_tx.send(Some(request.record));
+                Ok(())
+            }
+            Some(_) => Err("card is not blank".to_string()),
+            None => Err("no card present".to_string()),
+        };
+
+        let _ = request.response.send(result);
+    }
 
-                    select! {
-                        _ = response.closed() => break,
-                        result = self.ui_rx.changed() => {
-                            if result.is_err() {
-                                break;
-                            }
+    async fn handle_request(&mut self, request: NfcReaderRequest) {
+        match request {
+            NfcReaderRequest::WaitForCard(mut response) => loop {
+                if let Some(card) = self.ui_rx.borrow().clone() {
+                    self.reported_uid = Some(card.uid);
+                    let _ = response.send(card.uid);
+                    break;
+                }
+
+                select! {
+                    _ = response.closed() => break,
+                    result = self.ui_rx.changed() => {
+                        if result.is_err() {
+                            break;
                         }
                     }
-                },
+                }
+            },
 
-                NfcReaderRequest::WaitForRemoval(mut response) => loop {
-                    if self.ui_rx.borrow().is_none() {
-                        let _ = response.send(());
-                        break;
-                    }
+            NfcReaderRequest::WaitForRemoval(mut response) => loop {
+                // Swapping in a different card counts as removal as well.
+                if self.ui_rx.borrow().as_ref().map(|card| card.uid) != self.reported_uid {
+                    let _ = response.send(());
+                    break;
+                }
 
-                    select! {
-                        _ = response.closed() => break,
-                        result = self.ui_rx.changed() => {
-                            if result.is_err() {
-                                break;
-                            }
+                select! {
+                    _ = response.closed() => break,
+                    result = self.ui_rx.changed() => {
+                        if result.is_err() {
+                            break;
                         }
                     }
-                },
+                }
+            },
 
-                NfcReaderRequest::ReadNdefText(response) => match self.ui_rx.borrow().clone() {
-                    Some(card) => {
-                        let _ = response.send(Ok(card.data));
-                    }
-                    None => {
-                        let _ = response.send(Err("no card present".to_string()));
-                    }
-                },
-            }
+            NfcReaderRequest::ReadNdefText(response) => match self.ui_rx.borrow().clone() {
+                Some(card) => {
+                    let _ = response.send(read_config_payload(&card));
+                }
+                None => {
+                    let _ = response.send(Err("no card present".to_string()));
+                }
+            },
         }
-
-        Ok(())
     }
 }
 
@@ -81,3 +115,11 @@ impl IntoSubsystem<Error> for NfcReaderTask {
         Ok(())
     }
 }
+
+/// Reads the config payload from the first record of the card which carries
+/// one.
+fn read_config_payload(card: &EmulatedCard) -> Result<String, String> {
+    let records = ndef::parse_message(&card.ndef_message()).map_err(|error| error.to_string())?;
+
+    ndef::config_payload(&records).ok_or_else(|| "card carries no config record".to_string())
+}
@@ -1,9 +1,13 @@
-use crate::hardware::buttons::Button;
+use crate::hardware::buttons::{Button, ButtonEvent};
+use crate::hardware::emulated::library::{format_uid, random_uid, CardLibrary, SavedCard};
 use crate::hardware::nfc::NfcUid;
+use crate::ndef::{self, ConfigRecord, RecordKind};
 use anyhow::Result;
 use eframe::epaint::Color32;
-use egui::{Sense, TextEdit, ViewportBuilder, ViewportCommand};
+use egui::{Key, ScrollArea, Sense, TextEdit, ViewportBuilder, ViewportCommand};
 use hex::FromHex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 use std::thread;
 use std::thread::sleep;
 use std::time::Duration;
@@ -14,12 +18,18 @@ use tokio_util::sync::CancellationToken;
 pub struct UiChannels {
     pub led_color_rx: watch::Receiver<Color32>,
     pub emulated_card_tx: watch::Sender<Option<EmulatedCard>>,
-    pub button_tx: mpsc::Sender<Button>,
+    pub written_card_rx: watch::Receiver<Option<ConfigRecord>>,
+    pub button_tx: mpsc::Sender<ButtonEvent>,
 }
 
-pub fn run_ui(shutdown_token: CancellationToken, channels: UiChannels) -> Result<()> {
+pub fn run_ui(
+    shutdown_token: CancellationToken,
+    channels: UiChannels,
+    buttons: Vec<Button>,
+    library_path: PathBuf,
+) -> Result<()> {
     let viewport = ViewportBuilder::default()
-        .with_inner_size([400., 400.])
+        .with_inner_size([400., 520.])
         .with_resizable(false);
 
     let options = eframe::NativeOptions {
@@ -34,6 +44,8 @@ pub fn run_ui(shutdown_token: CancellationToken, channels: UiChannels) -> Result
                 cc,
                 shutdown_token,
                 channels,
+                buttons,
+                library_path,
             )))
         }),
     )
@@ -42,10 +54,25 @@ pub fn run_ui(shutdown_token: CancellationToken, channels: UiChannels) -> Result
     Ok(())
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmulatedCard {
     pub uid: NfcUid,
     pub data: String,
+    #[serde(default)]
+    pub record_kind: RecordKind,
+}
+
+impl EmulatedCard {
+    /// Returns the NDEF message stored on the card, holding the data in a
+    /// single record of the chosen kind.
+    pub fn ndef_message(&self) -> Vec<u8> {
+        let record = ConfigRecord {
+            payload: self.data.clone(),
+            kind: self.record_kind,
+        };
+
+        ndef::encode_message(&[record.to_record()])
+    }
 }
 
 struct BloopBoxEmulator {
@@ -53,7 +80,16 @@ struct BloopBoxEmulator {
     scanning: bool,
     uid_input: String,
     tag_data_input: String,
+    record_kind: RecordKind,
     uid: Option<NfcUid>,
+    buttons: Vec<Button>,
+    held_buttons: Vec<Button>,
+    library: CardLibrary,
+    library_path: PathBuf,
+    card_name_input: String,
+    file_path_input: String,
+    /// Outcome of the last library action.
+    library_status: Option<String>,
 }
 
 impl BloopBoxEmulator {
@@ -61,6 +97,8 @@ impl BloopBoxEmulator {
         cc: &eframe::CreationContext<'_>,
         shutdown_token: CancellationToken,
         channels: UiChannels,
+        buttons: Vec<Button>,
+        library_path: PathBuf,
     ) -> Self {
         cc.egui_ctx.set_pixels_per_point(1.2);
 
@@ -81,15 +119,214 @@ impl BloopBoxEmulator {
             sleep(Duration::from_millis(100));
         });
 
+        let (library, library_status) = match CardLibrary::load(&library_path) {
+            Ok(library) => (library, None),
+            Err(error) => (CardLibrary::default(), Some(format!("{error:#}"))),
+        };
+
         Self {
             channels,
             scanning: false,
             uid_input: Default::default(),
             tag_data_input: Default::default(),
+            record_kind: Default::default(),
             uid: None,
+            buttons,
+            held_buttons: Vec::new(),
+            library,
+            library_path,
+            card_name_input: Default::default(),
+            file_path_input: Default::default(),
+            library_status,
+        }
+    }
+
+    /// Returns the card described by the inputs, if the UID is valid.
+    fn current_card(&self) -> Option<EmulatedCard> {
+        Some(EmulatedCard {
+            uid: self.uid?,
+            data: self.tag_data_input.clone(),
+            record_kind: self.record_kind,
+        })
+    }
+
+    /// Presents the current card while held, and removes it once released.
+    fn update_scan(&mut self, held: bool) {
+        if held == self.scanning {
+            return;
+        }
+
+        self.scanning = held;
+        let card = if held { self.current_card() } else { None };
+        let _ = self.channels.emulated_card_tx.send(card);
+    }
+
+    /// Takes over a saved card into the inputs.
+    fn load_card(&mut self, saved: SavedCard) {
+        self.card_name_input = saved.name;
+        self.uid_input = format_uid(saved.card.uid.as_bytes());
+        self.tag_data_input = saved.card.data;
+        self.record_kind = saved.card.record_kind;
+        self.parse_uid_input();
+    }
+
+    fn save_card(&mut self) {
+        let Some(card) = self.current_card() else {
+            return;
+        };
+
+        self.library.insert(SavedCard {
+            name: self.card_name_input.trim().to_string(),
+            card,
+        });
+        self.persist_library();
+    }
+
+    fn delete_card(&mut self) {
+        self.library.remove(self.card_name_input.trim());
+        self.persist_library();
+    }
+
+    fn persist_library(&mut self) {
+        self.library_status = self
+            .library
+            .save(&self.library_path)
+            .err()
+            .map(|error| format!("{error:#}"));
+    }
+
+    fn import_library(&mut self, path: &Path) {
+        match CardLibrary::load(path) {
+            Ok(imported) => {
+                let count = self.library.merge(imported);
+                self.persist_library();
+
+                if self.library_status.is_none() {
+                    self.library_status = Some(format!("Imported {count} cards"));
+                }
+            }
+            Err(error) => self.library_status = Some(format!("{error:#}")),
         }
     }
 
+    fn export_library(&mut self, path: &Path) {
+        self.library_status = Some(match self.library.save(path) {
+            Ok(()) => format!("Exported {} cards", self.library.cards.len()),
+            Err(error) => format!("{error:#}"),
+        });
+    }
+
+    /// Shows the saved cards, returning the one held down to tap it.
+    fn library_ui(&mut self, ui: &mut egui::Ui) -> Option<SavedCard> {
+        let mut tapped = None;
+
+        ui.collapsing("Card Library", |ui| {
+            ScrollArea::vertical().max_height(80.0).show(ui, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    for saved in &self.library.cards {
+                        if ui.button(&saved.name).is_pointer_button_down_on() {
+                            tapped = Some(saved.clone());
+                        }
+                    }
+                });
+            });
+
+            ui.horizontal(|ui| {
+                let name = self.card_name_input.trim();
+                let can_save = self.uid.is_some() && !name.is_empty();
+                let can_delete = self.library.get(name).is_some();
+
+                ui.add(
+                    TextEdit::singleline(&mut self.card_name_input)
+                        .hint_text("Name")
+                        .desired_width(160.0),
+                );
+
+                if ui
+                    .add_enabled(can_save, egui::Button::new("Save"))
+                    .clicked()
+                {
+                    self.save_card();
+                }
+
+                if ui
+                    .add_enabled(can_delete, egui::Button::new("Delete"))
+                    .clicked()
+                {
+                    self.delete_card();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.add(
+                    TextEdit::singleline(&mut self.file_path_input)
+                        .hint_text("cards.toml")
+                        .desired_width(160.0),
+                );
+                let path = PathBuf::from(self.file_path_input.trim());
+                let has_path = !path.as_os_str().is_empty();
+
+                if ui
+                    .add_enabled(has_path, egui::Button::new("Import"))
+                    .clicked()
+                {
+                    self.import_library(&path);
+                }
+
+                if ui
+                    .add_enabled(has_path, egui::Button::new("Export"))
+                    .clicked()
+                {
+                    self.export_library(&path);
+                }
+            });
+
+            if let Some(status) = &self.library_status {
+                ui.label(status);
+            }
+        });
+
+        tapped
+    }
+
+    /// Takes over a record written to the card by the box, sending the
+    /// updated card if it is still presented.
+    fn apply_written_card(&mut self) {
+        if !self.channels.written_card_rx.has_changed().unwrap_or(false) {
+            return;
+        }
+
+        let Some(record) = self.channels.written_card_rx.borrow_and_update().clone() else {
+            return;
+        };
+
+        self.tag_data_input = record.payload;
+        self.record_kind = record.kind;
+
+        if self.scanning && self.uid.is_some() {
+            let _ = self.channels.emulated_card_tx.send(self.current_card());
+        }
+    }
+
+    /// Reports presses and releases of a button held down through the UI.
+    fn update_button(&mut self, button: Button, held: bool) {
+        if held == self.held_buttons.contains(&button) {
+            return;
+        }
+
+        if held {
+            self.held_buttons.push(button.clone());
+        } else {
+            self.held_buttons
+                .retain(|held_button| *held_button != button);
+        }
+
+        let _ = self.channels.button_tx.blocking_send(ButtonEvent {
+            button,
+            pressed: held,
+        });
+    }
+
     fn parse_uid_input(&mut self) {
         let cleaned: String = self.uid_input.chars().filter(|c| *c != ':').collect();
         let len = cleaned.len();
@@ -105,15 +342,23 @@ impl BloopBoxEmulator {
 
 impl eframe::App for BloopBoxEmulator {
     fn ui(&mut self, ui: &mut egui::Ui, _frame: &mut eframe::Frame) {
+        self.apply_written_card();
+        let mut scan_held = false;
+
         egui::Frame::central_panel(ui.style()).show(ui, |ui| {
             ui.horizontal(|ui| {
                 ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
-                    if ui.button("Vol -").clicked() {
-                        let _ = self.channels.button_tx.blocking_send(Button::VolumeDown);
-                    }
-
-                    if ui.button("Vol +").clicked() {
-                        let _ = self.channels.button_tx.blocking_send(Button::VolumeUp);
+                    for button in self.buttons.clone() {
+                        // The arrow keys allow holding both volume buttons at
+                        // once.
+                        let (label, key) = match button.name() {
+                            Button::VOLUME_DOWN => ("Vol -".to_string(), Some(Key::ArrowDown)),
+                            Button::VOLUME_UP => ("Vol +".to_string(), Some(Key::ArrowUp)),
+                            name => (name.to_string(), None),
+                        };
+                        let held = ui.button(label).is_pointer_button_down_on()
+                            || key.is_some_and(|key| ui.input(|input| input.key_down(key)));
+                        self.update_button(button, held);
                     }
                 });
 
@@ -123,30 +368,36 @@ impl eframe::App for BloopBoxEmulator {
                     ui.painter()
                         .rect_filled(rect, 12.0, *self.channels.led_color_rx.borrow());
 
-                    if ui
+                    scan_held = ui
                         .add_enabled(self.uid.is_some(), egui::Button::new("Scan UID"))
-                        .is_pointer_button_down_on()
-                    {
-                        if !self.scanning {
-                            self.scanning = true;
-
-                            if let Some(uid) = self.uid {
-                                let _ = self.channels.emulated_card_tx.send(Some(EmulatedCard {
-                                    uid,
-                                    data: self.tag_data_input.clone(),
-                                }));
-                            }
-                        }
-                    } else if self.scanning {
-                        self.scanning = false;
-                        let _ = self.channels.emulated_card_tx.send(None);
-                    }
+                        .is_pointer_button_down_on();
                 });
             });
 
             ui.separator();
 
-            ui.label("UID:");
+            if let Some(saved) = self.library_ui(ui) {
+                // Quick taps present the saved card for as long as they are
+                // held, loading it into the inputs first.
+                if !self.scanning {
+                    self.load_card(saved);
+                }
+
+                scan_held = true;
+            }
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("UID:");
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("Random").clicked() {
+                        self.uid_input = random_uid();
+                        self.parse_uid_input();
+                    }
+                });
+            });
             ui.add_space(5.0);
             let uid_response = ui.add_sized(
                 [ui.available_width(), 0.],
@@ -166,12 +417,19 @@ impl eframe::App for BloopBoxEmulator {
 
             ui.add_space(10.0);
 
-            ui.label("Tag Data:");
+            ui.horizontal(|ui| {
+                ui.label("Tag Data:");
+                ui.radio_value(&mut self.record_kind, RecordKind::Text, "Text");
+                ui.radio_value(&mut self.record_kind, RecordKind::Mime, "MIME");
+                ui.radio_value(&mut self.record_kind, RecordKind::Uri, "URI");
+            });
             ui.add_space(5.0);
             ui.add_sized(
                 ui.available_size(),
                 TextEdit::multiline(&mut self.tag_data_input).code_editor(),
             );
         });
+
+        self.update_scan(scan_held);
     }
 }
@@ -1,27 +1,508 @@
+use async_trait::async_trait;
+use hex::FromHex;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+use std::panic::RefUnwindSafe;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tokio::sync::mpsc;
 
-#[allow(dead_code)]
+/// An RGB color, written as `#rrggbb` or one of the basic color names in the
+/// config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color(u8, u8, u8);
+
+impl Color {
+    pub const RED: Self = Self(255, 0, 0);
+    pub const GREEN: Self = Self(0, 255, 0);
+    pub const BLUE: Self = Self(0, 0, 255);
+    pub const YELLOW: Self = Self(255, 255, 0);
+    pub const MAGENTA: Self = Self(255, 0, 255);
+    pub const CYAN: Self = Self(0, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self(r, g, b)
+    }
+
+    pub(super) fn rgb(&self) -> Rgb {
+        (self.0, self.1, self.2)
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "red" => return Ok(Self::RED),
+            "green" => return Ok(Self::GREEN),
+            "blue" => return Ok(Self::BLUE),
+            "yellow" => return Ok(Self::YELLOW),
+            "magenta" => return Ok(Self::MAGENTA),
+            "cyan" => return Ok(Self::CYAN),
+            _ => {}
+        }
+
+        let hex = value
+            .strip_prefix('#')
+            .ok_or_else(|| format!("invalid color: {value}"))?;
+        let [r, g, b] = <[u8; 3]>::from_hex(hex).map_err(|_| format!("invalid color: {value}"))?;
+
+        Ok(Self(r, g, b))
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+pub(super) type Rgb = (u8, u8, u8);
+
+const BLACK: Rgb = (0, 0, 0);
+const BREATHING_HALF_PERIOD: Duration = Duration::from_secs(2);
+const BLINK_ON: Duration = Duration::from_millis(200);
+const BLINK_OFF: Duration = Duration::from_millis(300);
+const BLINK_CODE_PAUSE: Duration = Duration::from_millis(1500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// Jump to the keyframe color and hold it for the duration.
+    Step,
+    /// Fade from the previous color to the keyframe color over the duration.
+    Fade,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keyframe {
+    color: Rgb,
+    duration: Duration,
+    transition: Transition,
+}
+
+impl Keyframe {
+    pub fn new(color: Color, duration: Duration, transition: Transition) -> Self {
+        Self {
+            color: color.rgb(),
+            duration,
+            transition,
+        }
+    }
+
+    pub fn off(duration: Duration) -> Self {
+        Self {
+            color: BLACK,
+            duration,
+            transition: Transition::Step,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Forever,
+    Times(u32),
+}
+
+/// What happens once a finite animation has completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finish {
+    /// Keep showing the color of the last keyframe.
+    Hold,
+    /// Return to the animation which was shown before.
+    Restore,
+}
+
+/// Presets which LED drivers may render with hardware patterns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Preset {
+    Breathing(Rgb),
+}
+
+/// A keyframe based LED animation.
+///
+/// The states used by the engine are available as presets, e.g.
+/// [`Animation::solid`] and [`Animation::breathing`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Animation {
+    keyframes: Vec<Keyframe>,
+    repeat: Repeat,
+    finish: Finish,
+    preset: Option<Preset>,
+}
+
+impl Animation {
+    pub fn new(keyframes: Vec<Keyframe>, repeat: Repeat, finish: Finish) -> Self {
+        Self {
+            keyframes,
+            repeat,
+            finish,
+            preset: None,
+        }
+    }
+
+    pub fn off() -> Self {
+        Self::new(
+            vec![Keyframe::off(Duration::ZERO)],
+            Repeat::Times(1),
+            Finish::Hold,
+        )
+    }
+
+    pub fn solid(color: Color) -> Self {
+        Self::new(
+            vec![Keyframe::new(color, Duration::ZERO, Transition::Step)],
+            Repeat::Times(1),
+            Finish::Hold,
+        )
+    }
+
+    pub fn breathing(color: Color) -> Self {
+        Self {
+            preset: Some(Preset::Breathing(color.rgb())),
+            ..Self::new(
+                vec![
+                    Keyframe::new(color, BREATHING_HALF_PERIOD, Transition::Fade),
+                    Keyframe {
+                        color: BLACK,
+                        duration: BREATHING_HALF_PERIOD,
+                        transition: Transition::Fade,
+                    },
+                ],
+                Repeat::Forever,
+                Finish::Hold,
+            )
+        }
+    }
+
+    /// Blinks the given number of times, then returns to the previous
+    /// animation.
+    pub fn blink(color: Color, count: u32) -> Self {
+        Self::new(
+            vec![
+                Keyframe::new(color, BLINK_ON, Transition::Step),
+                Keyframe::off(BLINK_OFF),
+            ],
+            Repeat::Times(count),
+            Finish::Restore,
+        )
+    }
+
+    /// Blinks the given number of times followed by a pause, forever.
+    pub fn blink_code(color: Color, count: u32) -> Self {
+        let mut keyframes = Vec::new();
+
+        for _ in 0..count {
+            keyframes.push(Keyframe::new(color, BLINK_ON, Transition::Step));
+            keyframes.push(Keyframe::off(BLINK_OFF));
+        }
+
+        keyframes.push(Keyframe::off(BLINK_CODE_PAUSE));
+        Self::new(keyframes, Repeat::Forever, Finish::Hold)
+    }
+
+    /// Alternates between two colors, showing each for the given duration.
+    pub fn alternate(first: Color, second: Color, duration: Duration) -> Self {
+        Self::new(
+            vec![
+                Keyframe::new(first, duration, Transition::Step),
+                Keyframe::new(second, duration, Transition::Step),
+            ],
+            Repeat::Forever,
+            Finish::Hold,
+        )
+    }
+
+    /// Fades from one color to another and holds the target color.
+    pub fn fade(from: Color, to: Color, duration: Duration) -> Self {
+        Self::new(
+            vec![
+                Keyframe::new(from, Duration::ZERO, Transition::Step),
+                Keyframe::new(to, duration, Transition::Fade),
+            ],
+            Repeat::Times(1),
+            Finish::Hold,
+        )
+    }
+
+    /// Shows a color briefly, then returns to the previous animation.
+    pub fn flash(color: Color, duration: Duration) -> Self {
+        Self::new(
+            vec![Keyframe::new(color, duration, Transition::Step)],
+            Repeat::Times(1),
+            Finish::Restore,
+        )
+    }
+
+    #[cfg_attr(feature = "hardware-emulation", allow(dead_code))]
+    pub(super) fn preset(&self) -> Option<Preset> {
+        self.preset
+    }
+
+    fn cycle_duration(&self) -> Duration {
+        self.keyframes
+            .iter()
+            .map(|keyframe| keyframe.duration)
+            .sum()
+    }
+
+    /// Whether the animation has completed after the given time.
+    fn is_finished(&self, elapsed: Duration) -> bool {
+        match self.repeat {
+            Repeat::Forever => self.cycle_duration().is_zero(),
+            Repeat::Times(count) => elapsed >= self.cycle_duration() * count,
+        }
+    }
+
+    /// Returns the color at the given time since the animation started.
+    fn sample(&self, elapsed: Duration) -> Rgb {
+        let Some(last) = self.keyframes.last() else {
+            return BLACK;
+        };
+        let cycle = self.cycle_duration();
+
+        if self.is_finished(elapsed) {
+            return last.color;
+        }
+
+        let mut offset = Duration::from_nanos((elapsed.as_nanos() % cycle.as_nanos()) as u64);
+        let mut previous = last.color;
+
+        for keyframe in &self.keyframes {
+            if offset < keyframe.duration {
+                return match keyframe.transition {
+                    Transition::Step => keyframe.color,
+                    Transition::Fade => interpolate(
+                        previous,
+                        keyframe.color,
+                        offset.as_secs_f32() / keyframe.duration.as_secs_f32(),
+                    ),
+                };
+            }
+
+            offset -= keyframe.duration;
+            previous = keyframe.color;
+        }
+
+        last.color
+    }
+}
+
+fn interpolate(from: Rgb, to: Rgb, t: f32) -> Rgb {
+    let channel = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * t).round() as u8;
+
+    (
+        channel(from.0, to.0),
+        channel(from.1, to.1),
+        channel(from.2, to.2),
+    )
+}
+
+/// Scales a color by a brightness factor between 0.0 and 1.0.
+pub(super) fn scale(rgb: Rgb, factor: f32) -> Rgb {
+    let channel = |value: u8| (value as f32 * factor.clamp(0.0, 1.0)).round() as u8;
+    (channel(rgb.0), channel(rgb.1), channel(rgb.2))
+}
+
+/// Brightness settings shared by all LED drivers.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BrightnessConfig {
+    /// Brightness between 0.0 and 1.0, relative to the driver's maximum.
+    brightness: Option<f32>,
+    /// Brightness of breathing patterns, which look dimmer than steady colors
+    /// at the same brightness.
+    breathing_brightness: Option<f32>,
+    /// Dimmed brightness during a time window, e.g. at night.
+    dimming: Option<DimmingSchedule>,
+}
+
+/// Brightness used by a driver unless configured otherwise.
+#[derive(Debug, Clone, Copy)]
+pub struct DefaultBrightness {
+    pub steady: f32,
+    pub breathing: f32,
+}
+
+impl DefaultBrightness {
+    pub const FULL: Self = Self {
+        steady: 1.0,
+        breathing: 1.0,
+    };
+}
+
+impl BrightnessConfig {
+    /// Returns the brightness of the animation at the current time.
+    pub(super) fn current(&self, animation: &Animation, defaults: DefaultBrightness) -> f32 {
+        match &self.dimming {
+            Some(dimming) if dimming.is_active(SystemTime::now()) => dimming.brightness,
+            _ => match animation.preset() {
+                Some(Preset::Breathing(_)) => {
+                    self.breathing_brightness.unwrap_or(defaults.breathing)
+                }
+                _ => self.brightness.unwrap_or(defaults.steady),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DimmingSchedule {
+    start: TimeOfDay,
+    end: TimeOfDay,
+    brightness: f32,
+    /// Offset of the local time of the window from UTC.
+    #[serde(default)]
+    utc_offset: UtcOffset,
+}
+
+impl DimmingSchedule {
+    fn is_active(&self, now: SystemTime) -> bool {
+        let minute = self.utc_offset.minute_of_day(now);
+
+        if self.start.0 <= self.end.0 {
+            (self.start.0..self.end.0).contains(&minute)
+        } else {
+            minute >= self.start.0 || minute < self.end.0
+        }
+    }
+}
+
+/// Minute of the day, written as `HH:MM` in the config.
+#[derive(Debug, Clone, Copy)]
+struct TimeOfDay(u16);
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        let (hours, minutes) = parse_hours_minutes(&raw)
+            .ok_or_else(|| D::Error::custom(format!("invalid time of day: {raw}")))?;
+
+        Ok(Self(hours * 60 + minutes))
+    }
+}
+
+/// Offset from UTC in minutes, written as `+HH:MM` or `-HH:MM` in the config.
+#[derive(Debug, Clone, Copy, Default)]
+struct UtcOffset(i32);
+
+impl UtcOffset {
+    const MINUTES_PER_DAY: i64 = 24 * 60;
+
+    fn minute_of_day(self, time: SystemTime) -> u16 {
+        let minutes = time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() / 60)
+            .unwrap_or_default() as i64;
+
+        (minutes + self.0 as i64).rem_euclid(Self::MINUTES_PER_DAY) as u16
+    }
+}
+
+impl<'de> Deserialize<'de> for UtcOffset {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = String::deserialize(deserializer)?;
+        let offset = raw
+            .strip_prefix('+')
+            .map(|offset| (1, offset))
+            .or_else(|| raw.strip_prefix('-').map(|offset| (-1, offset)))
+            .and_then(|(sign, offset)| {
+                let (hours, minutes) = parse_hours_minutes(offset)?;
+                Some(sign * (hours * 60 + minutes) as i32)
+            })
+            .ok_or_else(|| D::Error::custom(format!("invalid UTC offset: {raw}")))?;
+
+        Ok(Self(offset))
+    }
+}
+
+fn parse_hours_minutes(raw: &str) -> Option<(u16, u16)> {
+    raw.split_once(':')
+        .and_then(|(hours, minutes)| {
+            Some((hours.parse::<u16>().ok()?, minutes.parse::<u16>().ok()?))
+        })
+        .filter(|(hours, minutes)| *hours < 24 && *minutes < 60)
+}
+
+/// Tracks the running animation for an LED driver.
 #[derive(Debug)]
-pub enum Color {
-    Red,
-    Green,
-    Blue,
-    Yellow,
-    Magenta,
-    Cyan,
+pub(super) struct AnimationPlayer {
+    current: Animation,
+    started: Instant,
+    previous: Option<(Animation, Instant)>,
 }
 
-impl Color {
-    pub(super) fn rgb(&self) -> (u8, u8, u8) {
-        match self {
-            Self::Red => (255, 0, 0),
-            Self::Green => (0, 255, 0),
-            Self::Blue => (0, 0, 255),
-            Self::Yellow => (255, 255, 0),
-            Self::Magenta => (255, 0, 255),
-            Self::Cyan => (0, 255, 255),
+impl AnimationPlayer {
+    pub fn new() -> Self {
+        Self {
+            current: Animation::off(),
+            started: Instant::now(),
+            previous: None,
+        }
+    }
+
+    pub fn set(&mut self, animation: Animation) {
+        let now = Instant::now();
+
+        if animation.finish == Finish::Restore {
+            // Only the last persistent animation is restored, nested restoring
+            // animations replace each other.
+            if self.current.finish != Finish::Restore {
+                let current = std::mem::replace(&mut self.current, animation);
+                self.previous = Some((current, self.started));
+            } else {
+                self.current = animation;
+            }
+        } else {
+            self.current = animation;
+            self.previous = None;
         }
+
+        self.started = now;
+    }
+
+    /// Returns the animation to show right now, moving on to the previous
+    /// animation once a restoring animation has finished.
+    pub fn current(&mut self) -> &Animation {
+        if self.current.finish == Finish::Restore
+            && self.current.is_finished(self.started.elapsed())
+        {
+            let (previous, started) = self
+                .previous
+                .take()
+                .unwrap_or_else(|| (Animation::off(), Instant::now()));
+            self.current = previous;
+            self.started = started;
+        }
+
+        &self.current
+    }
+
+    pub fn color(&mut self) -> Rgb {
+        let started = self.started;
+        self.current().sample(started.elapsed())
+    }
+
+    /// Whether the color will not change until a new animation is set.
+    pub fn is_steady(&mut self) -> bool {
+        let started = self.started;
+        let current = self.current();
+
+        current.finish == Finish::Hold && current.is_finished(started.elapsed())
     }
 }
 
@@ -31,41 +512,89 @@ pub enum Error {
     Disconnected,
 }
 
-#[derive(Debug, Clone)]
+/// Destination of the animations of a [`LedController`], the LED driver task
+/// outside of tests.
+///
+/// Controllers are handed to the supervised runtime thread, hence the unwind
+/// safety bound.
+#[async_trait]
+pub trait LedOutput: Send + Sync + RefUnwindSafe {
+    async fn show(&self, animation: Animation) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl LedOutput for mpsc::Sender<LedState> {
+    async fn show(&self, animation: Animation) -> Result<(), Error> {
+        self.send(animation).await.map_err(|_| Error::Disconnected)
+    }
+}
+
+#[derive(Clone)]
 pub struct LedController {
-    tx: mpsc::Sender<LedState>,
+    output: Arc<dyn LedOutput>,
 }
 
 impl LedController {
-    pub(super) fn new(tx: mpsc::Sender<LedState>) -> Self {
-        Self { tx }
+    pub fn new(output: impl LedOutput + 'static) -> Self {
+        Self {
+            output: Arc::new(output),
+        }
     }
 
-    pub async fn set_static(&self, color: Color) -> Result<(), Error> {
-        self.tx
-            .send(LedState::Static(color))
-            .await
-            .map_err(|_| Error::Disconnected)
+    pub async fn set_off(&self) -> Result<(), Error> {
+        self.set_animation(Animation::off()).await
     }
 
-    pub async fn set_breathing(&self, color: Color) -> Result<(), Error> {
-        self.tx
-            .send(LedState::Breathing(color))
-            .await
-            .map_err(|_| Error::Disconnected)
+    pub async fn set_animation(&self, animation: Animation) -> Result<(), Error> {
+        self.output.show(animation).await
     }
+}
 
-    pub async fn set_off(&self) -> Result<(), Error> {
-        self.tx
-            .send(LedState::Off)
-            .await
-            .map_err(|_| Error::Disconnected)
+pub(super) type LedState = Animation;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dimming(utc_offset: &str) -> DimmingSchedule {
+        toml::from_str(&format!(
+            "start = \"22:00\"\nend = \"07:00\"\nbrightness = 0.3\nutc_offset = \"{utc_offset}\""
+        ))
+        .unwrap()
     }
-}
 
-#[derive(Debug)]
-pub(super) enum LedState {
-    Off,
-    Static(Color),
-    Breathing(Color),
+    #[test]
+    fn dims_in_local_time() {
+        // 21:30 UTC.
+        let now = UNIX_EPOCH + Duration::from_secs((21 * 60 + 30) * 60);
+
+        assert!(!dimming("+00:00").is_active(now));
+        assert!(dimming("+01:00").is_active(now));
+        assert!(!dimming("-10:00").is_active(now));
+        assert!(dimming("+03:00").is_active(now));
+        assert!(!dimming("+10:00").is_active(now));
+    }
+
+    #[test]
+    fn rejects_invalid_utc_offsets() {
+        for utc_offset in ["01:00", "+24:00", "+01:60", "+1"] {
+            assert!(toml::from_str::<DimmingSchedule>(&format!(
+                "start = \"22:00\"\nend = \"07:00\"\nbrightness = 0.3\nutc_offset = \"{utc_offset}\""
+            ))
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn uses_breathing_brightness_for_breathing() {
+        let defaults = DefaultBrightness {
+            steady: 0.25,
+            breathing: 0.5,
+        };
+        let config = BrightnessConfig::default();
+        let breathing = Animation::breathing(Color(255, 255, 255));
+
+        assert_eq!(config.current(&breathing, defaults), 0.5);
+        assert_eq!(config.current(&Animation::off(), defaults), 0.25);
+    }
 }
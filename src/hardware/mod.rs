@@ -1,12 +1,14 @@
+use crate::gestures::ButtonAction;
 use crate::hardware::buttons::ButtonReceiver;
 use crate::hardware::led::LedController;
-use crate::hardware::nfc::NfcReader;
+use crate::hardware::nfc::{NfcReaderHandle, ReaderHealth};
 use anyhow::{Context, Result};
 use directories::ProjectDirs;
 use std::env;
 use std::panic::UnwindSafe;
 use std::path::PathBuf;
 use tokio::fs;
+use tokio::sync::mpsc;
 use tokio_graceful_shutdown::SubsystemHandle;
 
 #[cfg(feature = "hardware-emulation")]
@@ -24,27 +26,37 @@ mod pi;
 
 pub struct Peripherals {
     pub led_controller: LedController,
-    pub nfc_reader: NfcReader,
+    pub nfc_readers: Vec<NfcReaderHandle>,
+    pub nfc_health_rx: mpsc::Receiver<ReaderHealth>,
     pub button_receiver: ButtonReceiver,
+    /// Actions reported by hardware without gestures, like the detents of a
+    /// rotary encoder.
+    pub action_receiver: mpsc::Receiver<ButtonAction>,
 }
 
 pub type InitSubsystems = Box<dyn FnOnce() -> Result<StartSubsystems> + Send + UnwindSafe>;
 pub type StartSubsystems = Box<dyn FnOnce(&SubsystemHandle) + Send>;
 
 pub async fn data_path() -> Result<PathBuf> {
+    let data_dir = data_dir()?;
+
+    if fs::metadata(&data_dir).await.is_err() {
+        fs::create_dir_all(&data_dir)
+            .await
+            .with_context(|| format!("failed to create data dir {}", data_dir.display()))?;
+    }
+
+    Ok(data_dir)
+}
+
+/// Returns the data dir without making sure it exists.
+fn data_dir() -> Result<PathBuf> {
     if let Ok(dir) = env::var("BLOOP_BOX_DATA_DIR") {
         return Ok(PathBuf::from(dir));
     }
 
     let project_dirs =
         ProjectDirs::from("", "", "bloop-box").context("failed to get project dirs")?;
-    let data_dir = project_dirs.data_dir();
-
-    if fs::metadata(data_dir).await.is_err() {
-        fs::create_dir_all(data_dir)
-            .await
-            .with_context(|| format!("failed to create data dir {}", data_dir.display()))?;
-    }
 
-    Ok(data_dir.to_path_buf())
+    Ok(project_dirs.data_dir().to_path_buf())
 }
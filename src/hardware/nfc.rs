@@ -1,6 +1,79 @@
-//! NFC types, re-exported from the client framework.
+//! NFC types, re-exported from the client framework, and the writer for
+//! config cards.
 
+use crate::ndef::ConfigRecord;
+use anyhow::{anyhow, Result};
 pub use bloop_client_framework::nfc::NfcReader;
-#[cfg(feature = "hardware-emulation")]
 pub use bloop_client_framework::nfc::NfcReaderRequest;
 pub use bloop_protocol::NfcUid;
+use tokio::sync::{mpsc, oneshot};
+
+/// A reader, along with the writer served by the same backend.
+#[derive(Debug)]
+pub struct NfcReaderHandle {
+    /// ID the reader is referred to by in logs.
+    pub id: String,
+    pub reader: NfcReader,
+    pub writer: NfcWriter,
+}
+
+impl NfcReaderHandle {
+    pub fn channel(
+        id: impl Into<String>,
+    ) -> (
+        Self,
+        mpsc::Receiver<NfcReaderRequest>,
+        mpsc::Receiver<NfcWriteRequest>,
+    ) {
+        let (reader, request_rx) = NfcReader::channel();
+        let (writer, write_rx) = NfcWriter::channel();
+        let handle = Self {
+            id: id.into(),
+            reader,
+            writer,
+        };
+
+        (handle, request_rx, write_rx)
+    }
+}
+
+/// Health of a reader, reported by its backend whenever it changes.
+#[derive(Debug, Clone)]
+pub struct ReaderHealth {
+    pub reader_id: String,
+    pub healthy: bool,
+}
+
+#[derive(Debug)]
+pub struct NfcWriteRequest {
+    pub record: ConfigRecord,
+    pub response: oneshot::Sender<Result<(), String>>,
+}
+
+/// Writes config records to cards, served by the same backend as the reader.
+#[derive(Debug, Clone)]
+pub struct NfcWriter {
+    tx: mpsc::Sender<NfcWriteRequest>,
+}
+
+impl NfcWriter {
+    pub fn channel() -> (Self, mpsc::Receiver<NfcWriteRequest>) {
+        let (tx, rx) = mpsc::channel(1);
+
+        (Self { tx }, rx)
+    }
+
+    /// Writes the record to the card in the field, which has to be blank.
+    pub async fn write(&self, record: ConfigRecord) -> Result<()> {
+        let (response, response_rx) = oneshot::channel();
+        self.tx
+            .send(NfcWriteRequest { record, response })
+            .await
+            .map_err(|_| anyhow!("NFC writer is no longer running"))?;
+
+        response_rx
+            .await
+            .map_err(|_| anyhow!("NFC writer is no longer running"))?
+            .map_err(|error| anyhow!(error))
+    }
+}
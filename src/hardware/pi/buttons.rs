@@ -1,15 +1,22 @@
-use crate::hardware::buttons::Button;
+use crate::gestures::ButtonAction;
+use crate::hardware::buttons::{Button, ButtonEvent};
+use crate::hardware::pi::encoder::{EncoderConfig, QuadratureDecoder};
 use anyhow::{Context, Error, Result};
-use gpiocdev::line::EdgeDetection;
+use gpiocdev::line::{Bias, EdgeDetection, EdgeKind, Value};
 use gpiocdev::tokio::AsyncRequest;
 use gpiocdev::Request;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio_graceful_shutdown::{FutureExt, IntoSubsystem, SubsystemHandle};
 use tracing::warn;
 
+/// Debounce period of the encoder lines, which change much faster than
+/// buttons.
+const ENCODER_DEBOUNCE_PERIOD: Duration = Duration::from_millis(1);
+
 #[derive(Debug, Deserialize)]
 pub struct ButtonsConfig {
     #[serde(default = "ButtonsConfig::default_gpio_dev_path")]
@@ -18,6 +25,12 @@ pub struct ButtonsConfig {
     volume_up_line: u32,
     #[serde(default = "ButtonsConfig::default_volume_down_line")]
     volume_down_line: u32,
+    /// GPIO lines by button name. Replaces the volume lines when set.
+    #[serde(default)]
+    lines: Option<HashMap<Button, u32>>,
+    /// Rotary encoder replacing the volume buttons.
+    #[serde(default)]
+    encoder: Option<EncoderConfig>,
 }
 
 impl Default for ButtonsConfig {
@@ -26,6 +39,8 @@ impl Default for ButtonsConfig {
             gpio_dev_path: Self::default_gpio_dev_path(),
             volume_up_line: Self::default_volume_up_line(),
             volume_down_line: Self::default_volume_down_line(),
+            lines: None,
+            encoder: None,
         }
     }
 }
@@ -42,47 +57,118 @@ impl ButtonsConfig {
     fn default_volume_down_line() -> u32 {
         24
     }
+
+    fn lines(&self) -> HashMap<u32, Button> {
+        let mut lines = match &self.lines {
+            Some(lines) => lines
+                .iter()
+                .map(|(button, line)| (*line, button.clone()))
+                .collect(),
+            None if self.encoder.is_some() => HashMap::new(),
+            None => HashMap::from([
+                (self.volume_up_line, Button::new(Button::VOLUME_UP)),
+                (self.volume_down_line, Button::new(Button::VOLUME_DOWN)),
+            ]),
+        };
+
+        if let Some(switch_line) = self
+            .encoder
+            .as_ref()
+            .and_then(|encoder| encoder.switch_line)
+        {
+            lines.insert(switch_line, Button::new(Button::MUTE));
+        }
+
+        lines
+    }
 }
 
 pub struct Buttons {
-    tx: mpsc::Sender<Button>,
+    tx: mpsc::Sender<ButtonEvent>,
+    action_tx: mpsc::Sender<ButtonAction>,
     request: AsyncRequest,
-    config: ButtonsConfig,
+    lines: HashMap<u32, Button>,
+    encoder: Option<QuadratureDecoder>,
 }
 
 impl Buttons {
-    pub fn new(tx: mpsc::Sender<Button>, config: ButtonsConfig) -> Result<Buttons> {
-        let request = AsyncRequest::new(
-            Request::builder()
-                .on_chip(config.gpio_dev_path.clone())
-                .with_consumer("bloop-box")
-                .with_lines(&[config.volume_up_line, config.volume_down_line])
-                .with_edge_detection(EdgeDetection::FallingEdge)
-                .with_debounce_period(Duration::from_millis(50))
-                .request()
-                .context("Failed to create GPIO request")?,
-        );
+    pub fn new(
+        tx: mpsc::Sender<ButtonEvent>,
+        action_tx: mpsc::Sender<ButtonAction>,
+        config: ButtonsConfig,
+    ) -> Result<Buttons> {
+        let lines = config.lines();
+        let offsets = lines.keys().copied().collect::<Vec<_>>();
+        let mut builder = Request::builder();
+        builder
+            .on_chip(config.gpio_dev_path.clone())
+            .with_consumer("bloop-box")
+            .with_lines(&offsets)
+            .with_edge_detection(EdgeDetection::BothEdges)
+            .with_debounce_period(Duration::from_millis(50));
+
+        if let Some(encoder) = &config.encoder {
+            builder
+                .with_lines(&[encoder.a_line, encoder.b_line])
+                .with_bias(Bias::PullUp)
+                .with_edge_detection(EdgeDetection::BothEdges)
+                .with_debounce_period(ENCODER_DEBOUNCE_PERIOD);
+        }
+
+        let request =
+            AsyncRequest::new(builder.request().context("Failed to create GPIO request")?);
+
+        let encoder = match &config.encoder {
+            Some(encoder) => {
+                let gpio = request.as_ref();
+                let a = gpio.value(encoder.a_line)? == Value::Active;
+                let b = gpio.value(encoder.b_line)? == Value::Active;
+                Some(QuadratureDecoder::new(encoder, a, b))
+            }
+            None => None,
+        };
 
         Ok(Self {
             tx,
+            action_tx,
             request,
-            config,
+            lines,
+            encoder,
         })
     }
 
     async fn listen(&mut self) -> Result<()> {
         loop {
             let event = self.request.read_edge_event().await?;
-            let button = match event.offset {
-                offset if offset == self.config.volume_up_line => Button::VolumeUp,
-                offset if offset == self.config.volume_down_line => Button::VolumeDown,
-                offset => {
-                    warn!("Unexpected GPIO line: {offset}");
+
+            if let Some(encoder) = &mut self.encoder {
+                if encoder.handles(event.offset) {
+                    let level = event.kind == EdgeKind::Rising;
+
+                    if let Some(action) = encoder.update(event.offset, level) {
+                        // Detents change the volume right away, gesture
+                        // mappings and repeats only apply to buttons.
+                        let _ = self.action_tx.send(action).await;
+                    }
+
                     continue;
                 }
+            }
+
+            let Some(button) = self.lines.get(&event.offset) else {
+                warn!("Unexpected GPIO line: {}", event.offset);
+                continue;
             };
 
-            let _ = self.tx.send(button).await;
+            // Buttons pull their lines low while pressed.
+            let pressed = event.kind == EdgeKind::Falling;
+            let _ = self
+                .tx
+                .send(ButtonEvent {
+                    button: button.clone(),
+                    pressed,
+                })
+                .await;
         }
     }
 }
@@ -0,0 +1,137 @@
+use crate::gestures::ButtonAction;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct EncoderConfig {
+    pub a_line: u32,
+    pub b_line: u32,
+    /// Line of the push switch, reported as the `mute` button.
+    #[serde(default)]
+    pub switch_line: Option<u32>,
+    /// Number of quadrature transitions between two detents.
+    #[serde(default = "EncoderConfig::default_steps_per_detent")]
+    steps_per_detent: u8,
+    /// Swaps the direction, for encoders wired the other way around.
+    #[serde(default)]
+    reverse: bool,
+}
+
+impl EncoderConfig {
+    fn default_steps_per_detent() -> u8 {
+        4
+    }
+}
+
+/// Change of the position for each pair of previous and current line states,
+/// indexed by `previous << 2 | current`. Invalid transitions, where both
+/// lines changed at once, are ignored.
+const TRANSITIONS: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+/// Decodes the quadrature signal of a rotary encoder into turns by one
+/// detent.
+#[derive(Debug)]
+pub struct QuadratureDecoder {
+    a_line: u32,
+    b_line: u32,
+    state: u8,
+    position: i8,
+    steps_per_detent: i8,
+    reverse: bool,
+}
+
+impl QuadratureDecoder {
+    pub fn new(config: &EncoderConfig, a: bool, b: bool) -> Self {
+        Self {
+            a_line: config.a_line,
+            b_line: config.b_line,
+            state: (a as u8) << 1 | b as u8,
+            position: 0,
+            steps_per_detent: config.steps_per_detent.clamp(1, 4) as i8,
+            reverse: config.reverse,
+        }
+    }
+
+    pub fn handles(&self, offset: u32) -> bool {
+        offset == self.a_line || offset == self.b_line
+    }
+
+    /// Processes a level change on one of the lines, returning the volume
+    /// action matching the direction once a full detent was turned.
+    pub fn update(&mut self, offset: u32, level: bool) -> Option<ButtonAction> {
+        let bit = if offset == self.a_line { 0b10 } else { 0b01 };
+        let state = if level {
+            self.state | bit
+        } else {
+            self.state & !bit
+        };
+
+        self.position += TRANSITIONS[(self.state << 2 | state) as usize];
+        self.state = state;
+
+        if self.position.abs() < self.steps_per_detent {
+            return None;
+        }
+
+        let clockwise = (self.position > 0) != self.reverse;
+        self.position = 0;
+
+        Some(if clockwise {
+            ButtonAction::VolumeUp
+        } else {
+            ButtonAction::VolumeDown
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decoder(reverse: bool) -> QuadratureDecoder {
+        let config = EncoderConfig {
+            a_line: 17,
+            b_line: 27,
+            switch_line: None,
+            steps_per_detent: 4,
+            reverse,
+        };
+
+        QuadratureDecoder::new(&config, true, true)
+    }
+
+    /// Turns the encoder by one detent, A leading B when clockwise.
+    fn turn(decoder: &mut QuadratureDecoder, clockwise: bool) -> Vec<Option<ButtonAction>> {
+        let (first, second) = if clockwise { (17, 27) } else { (27, 17) };
+
+        [
+            (first, false),
+            (second, false),
+            (first, true),
+            (second, true),
+        ]
+        .into_iter()
+        .map(|(offset, level)| decoder.update(offset, level))
+        .collect()
+    }
+
+    #[test]
+    fn reports_one_action_per_detent() {
+        let mut decoder = decoder(false);
+
+        assert_eq!(
+            turn(&mut decoder, true),
+            [None, None, None, Some(ButtonAction::VolumeUp)]
+        );
+        assert_eq!(
+            turn(&mut decoder, false),
+            [None, None, None, Some(ButtonAction::VolumeDown)]
+        );
+    }
+
+    #[test]
+    fn swaps_direction_when_reversed() {
+        let mut decoder = decoder(true);
+
+        assert_eq!(turn(&mut decoder, true)[3], Some(ButtonAction::VolumeDown));
+    }
+}
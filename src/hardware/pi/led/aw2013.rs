@@ -0,0 +1,127 @@
+use crate::hardware::led::{DefaultBrightness, Rgb};
+use crate::hardware::pi::led::LedDriver;
+use anyhow::Result;
+use aw2013::{Aw2013, Current, Timing};
+use linux_embedded_hal::I2cdev;
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::Duration;
+
+#[derive(Debug, Deserialize)]
+pub struct Aw2013Config {
+    #[serde(default = "Aw2013Config::default_i2c_dev_path")]
+    i2c_dev_path: PathBuf,
+    #[serde(default)]
+    current: DriveCurrent,
+}
+
+impl Default for Aw2013Config {
+    fn default() -> Self {
+        Self {
+            i2c_dev_path: Self::default_i2c_dev_path(),
+            current: DriveCurrent::default(),
+        }
+    }
+}
+
+impl Aw2013Config {
+    fn default_i2c_dev_path() -> PathBuf {
+        "/dev/i2c-1".into()
+    }
+}
+
+/// Maximum drive current of the AW2013 in milliamperes.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "u8")]
+struct DriveCurrent(u8);
+
+impl DriveCurrent {
+    fn current(self) -> Current {
+        match self.0 {
+            0 => Current::Zero,
+            5 => Current::Five,
+            10 => Current::Ten,
+            _ => Current::Fifteen,
+        }
+    }
+}
+
+impl Default for DriveCurrent {
+    fn default() -> Self {
+        Self(5)
+    }
+}
+
+impl TryFrom<u8> for DriveCurrent {
+    type Error = String;
+
+    fn try_from(milliamperes: u8) -> Result<Self, Self::Error> {
+        match milliamperes {
+            0 | 5 | 10 | 15 => Ok(Self(milliamperes)),
+            _ => Err(format!("unsupported drive current: {milliamperes} mA")),
+        }
+    }
+}
+
+pub struct Aw2013Driver {
+    aw2013: Aw2013<I2cdev>,
+}
+
+impl Aw2013Driver {
+    pub fn new(config: Aw2013Config) -> Result<Self> {
+        let i2c = I2cdev::new(config.i2c_dev_path)?;
+        let mut aw2013 = Aw2013::from_default_address(i2c, [config.current.current(); 3]);
+
+        aw2013.reset()?;
+        sleep(Duration::from_millis(10));
+        aw2013.enable()?;
+
+        Ok(Self { aw2013 })
+    }
+}
+
+impl LedDriver for Aw2013Driver {
+    fn set_color(&mut self, rgb: Rgb) -> Result<()> {
+        self.aw2013
+            .set_static_rgb([rgb.0, rgb.1, rgb.2], None, None)?;
+
+        Ok(())
+    }
+
+    fn has_breathing(&self) -> bool {
+        true
+    }
+
+    fn set_breathing(&mut self, rgb: Rgb) -> Result<()> {
+        self.aw2013.set_breathing_rgb(
+            [rgb.0, rgb.1, rgb.2],
+            &Timing {
+                delay: 0,
+                rise: 2,
+                hold: 2,
+                fall: 2,
+                off: 2,
+                cycles: 0,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// At full brightness, the AW2013 is glaring even at its lowest drive
+    /// current.
+    fn default_brightness(&self) -> DefaultBrightness {
+        DefaultBrightness {
+            steady: 0.25,
+            breathing: 0.5,
+        }
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        sleep(Duration::from_millis(10));
+        self.aw2013.reset()?;
+
+        Ok(())
+    }
+}
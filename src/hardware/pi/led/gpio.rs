@@ -0,0 +1,79 @@
+use crate::hardware::led::Rgb;
+use crate::hardware::pi::led::LedDriver;
+use anyhow::{Context, Result};
+use gpiocdev::line::Value;
+use gpiocdev::Request;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Channel value from which a channel is switched on.
+const THRESHOLD: u8 = 128;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GpioConfig {
+    gpio_dev_path: PathBuf,
+    red_line: u32,
+    green_line: u32,
+    blue_line: u32,
+    /// Whether the lines are pulled low to light the LED, as with a common
+    /// anode LED.
+    active_low: bool,
+}
+
+impl Default for GpioConfig {
+    fn default() -> Self {
+        Self {
+            gpio_dev_path: "/dev/gpiochip0".into(),
+            red_line: 17,
+            green_line: 27,
+            blue_line: 22,
+            active_low: false,
+        }
+    }
+}
+
+/// Drives an RGB LED on three GPIO lines.
+///
+/// Each channel is either on or off, so only the primary and mixed colors can
+/// be shown and fades turn into hard switches.
+pub struct GpioDriver {
+    request: Request,
+    lines: [u32; 3],
+}
+
+impl GpioDriver {
+    pub fn new(config: GpioConfig) -> Result<Self> {
+        let lines = [config.red_line, config.green_line, config.blue_line];
+        let mut builder = Request::builder();
+        builder
+            .on_chip(config.gpio_dev_path)
+            .with_consumer("bloop-box")
+            .with_lines(&lines)
+            .as_output(Value::Inactive);
+
+        if config.active_low {
+            builder.as_active_low();
+        }
+
+        let request = builder.request().context("Failed to create GPIO request")?;
+
+        Ok(Self { request, lines })
+    }
+}
+
+impl LedDriver for GpioDriver {
+    fn set_color(&mut self, rgb: Rgb) -> Result<()> {
+        for (line, channel) in self.lines.into_iter().zip([rgb.0, rgb.1, rgb.2]) {
+            let value = if channel >= THRESHOLD {
+                Value::Active
+            } else {
+                Value::Inactive
+            };
+
+            self.request.set_value(line, value)?;
+        }
+
+        Ok(())
+    }
+}
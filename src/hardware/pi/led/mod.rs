@@ -0,0 +1,153 @@
+use crate::hardware::led::{
+    scale, AnimationPlayer, BrightnessConfig, DefaultBrightness, LedState, Preset, Rgb,
+};
+use crate::hardware::pi::led::aw2013::{Aw2013Config, Aw2013Driver};
+use crate::hardware::pi::led::gpio::{GpioConfig, GpioDriver};
+use crate::hardware::pi::led::sysfs::{SysfsConfig, SysfsDriver};
+use crate::hardware::pi::led::ws2812::{Ws2812Config, Ws2812Driver};
+use crate::thread::{supervised_thread, SupervisedThread};
+use anyhow::Result;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+mod aw2013;
+mod gpio;
+mod sysfs;
+mod ws2812;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct LedControllerConfig {
+    #[serde(default)]
+    driver: DriverKind,
+    /// The AW2013 options live in the section itself for compatibility with
+    /// older config files.
+    #[serde(flatten)]
+    aw2013: Aw2013Config,
+    #[serde(default)]
+    ws2812: Ws2812Config,
+    #[serde(default)]
+    gpio: GpioConfig,
+    #[serde(default)]
+    sysfs: SysfsConfig,
+    #[serde(flatten)]
+    brightness: BrightnessConfig,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DriverKind {
+    #[default]
+    Aw2013,
+    Ws2812,
+    Gpio,
+    Sysfs,
+}
+
+/// Output stage for the status LED.
+///
+/// Colors passed to a driver are already scaled by the configured brightness.
+trait LedDriver {
+    fn set_color(&mut self, rgb: Rgb) -> Result<()>;
+
+    /// Whether the driver can render a breathing pattern on its own.
+    fn has_breathing(&self) -> bool {
+        false
+    }
+
+    fn set_breathing(&mut self, rgb: Rgb) -> Result<()> {
+        self.set_color(rgb)
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.set_color((0, 0, 0))
+    }
+
+    /// Brightness unless configured otherwise.
+    fn default_brightness(&self) -> DefaultBrightness {
+        DefaultBrightness::FULL
+    }
+}
+
+pub fn start_led_controller_thread(
+    rx: mpsc::Receiver<LedState>,
+    shutdown_token: CancellationToken,
+    config: LedControllerConfig,
+) -> Result<SupervisedThread> {
+    Ok(supervised_thread(
+        "led_controller",
+        shutdown_token,
+        move || led_controller_thread(rx, config),
+    )?)
+}
+
+/// Interval at which animations without a hardware pattern are rendered.
+const TICK: Duration = Duration::from_millis(20);
+
+/// Interval at which the brightness schedule is re-evaluated while the LED is
+/// steady.
+const BRIGHTNESS_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Output {
+    Static(Rgb),
+    Breathing(Rgb),
+}
+
+fn led_controller_thread(
+    mut rx: mpsc::Receiver<LedState>,
+    config: LedControllerConfig,
+) -> Result<()> {
+    let mut driver: Box<dyn LedDriver> = match config.driver {
+        DriverKind::Aw2013 => Box::new(Aw2013Driver::new(config.aw2013)?),
+        DriverKind::Ws2812 => Box::new(Ws2812Driver::new(config.ws2812)?),
+        DriverKind::Gpio => Box::new(GpioDriver::new(config.gpio)?),
+        DriverKind::Sysfs => Box::new(SysfsDriver::new(config.sysfs)?),
+    };
+
+    // Only used to wait for new states with a timeout.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()?;
+    let mut player = AnimationPlayer::new();
+    let mut output = None;
+
+    loop {
+        let brightness = config
+            .brightness
+            .current(player.current(), driver.default_brightness());
+        let hardware_pattern = match player.current().preset() {
+            Some(Preset::Breathing(rgb)) if driver.has_breathing() => Some(rgb),
+            _ => None,
+        };
+        let next_output = match hardware_pattern {
+            Some(rgb) => Output::Breathing(scale(rgb, brightness)),
+            None => Output::Static(scale(player.color(), brightness)),
+        };
+
+        if output.as_ref() != Some(&next_output) {
+            match next_output {
+                Output::Static(rgb) => driver.set_color(rgb)?,
+                Output::Breathing(rgb) => driver.set_breathing(rgb)?,
+            }
+
+            output = Some(next_output);
+        }
+
+        let wait = if player.is_steady() || hardware_pattern.is_some() {
+            BRIGHTNESS_CHECK_INTERVAL
+        } else {
+            TICK
+        };
+
+        match runtime.block_on(timeout(wait, rx.recv())) {
+            Ok(Some(animation)) => player.set(animation),
+            Ok(None) => break,
+            Err(_) => continue,
+        }
+    }
+
+    driver.shutdown()
+}
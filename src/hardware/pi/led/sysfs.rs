@@ -0,0 +1,84 @@
+use crate::hardware::led::Rgb;
+use crate::hardware::pi::led::LedDriver;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const LEDS_PATH: &str = "/sys/class/leds";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SysfsConfig {
+    /// Names of the LEDs below `/sys/class/leds` for each channel. Channels
+    /// without an LED are skipped.
+    red: Option<String>,
+    green: Option<String>,
+    blue: Option<String>,
+}
+
+impl Default for SysfsConfig {
+    fn default() -> Self {
+        Self {
+            red: Some("red".into()),
+            green: Some("green".into()),
+            blue: Some("blue".into()),
+        }
+    }
+}
+
+/// Drives LEDs exposed by the kernel's LED class, e.g. through the `gpio-leds`
+/// or `pwm-leds` device tree overlays.
+pub struct SysfsDriver {
+    channels: [Option<SysfsLed>; 3],
+}
+
+impl SysfsDriver {
+    pub fn new(config: SysfsConfig) -> Result<Self> {
+        let open = |name: Option<String>| name.map(|name| SysfsLed::open(&name)).transpose();
+
+        Ok(Self {
+            channels: [open(config.red)?, open(config.green)?, open(config.blue)?],
+        })
+    }
+}
+
+impl LedDriver for SysfsDriver {
+    fn set_color(&mut self, rgb: Rgb) -> Result<()> {
+        for (led, value) in self.channels.iter().zip([rgb.0, rgb.1, rgb.2]) {
+            if let Some(led) = led {
+                led.set(value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct SysfsLed {
+    brightness_path: PathBuf,
+    max_brightness: u32,
+}
+
+impl SysfsLed {
+    fn open(name: &str) -> Result<Self> {
+        let path = Path::new(LEDS_PATH).join(name);
+        let max_brightness_path = path.join("max_brightness");
+        let max_brightness = fs::read_to_string(&max_brightness_path)
+            .with_context(|| format!("Failed to read {}", max_brightness_path.display()))?
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid value in {}", max_brightness_path.display()))?;
+
+        Ok(Self {
+            brightness_path: path.join("brightness"),
+            max_brightness,
+        })
+    }
+
+    fn set(&self, value: u8) -> Result<()> {
+        let brightness = (value as u32 * self.max_brightness + 127) / 255;
+        fs::write(&self.brightness_path, brightness.to_string())
+            .with_context(|| format!("Failed to write {}", self.brightness_path.display()))
+    }
+}
@@ -0,0 +1,93 @@
+use crate::hardware::led::Rgb;
+use crate::hardware::pi::led::LedDriver;
+use anyhow::{Context, Result};
+use linux_embedded_hal::spidev::{SpiModeFlags, Spidev, SpidevOptions};
+use serde::Deserialize;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// SPI clock at which three SPI bits make up one WS2812 bit.
+const SPI_SPEED_HZ: u32 = 2_400_000;
+
+/// Number of zero bytes sent after the data to latch the colors, covering the
+/// 280 µs reset time of newer WS2812B revisions.
+const RESET_BYTES: usize = 90;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Ws2812Config {
+    spi_dev_path: PathBuf,
+    /// Number of LEDs on the strip or ring, all of which show the same color.
+    led_count: usize,
+}
+
+impl Default for Ws2812Config {
+    fn default() -> Self {
+        Self {
+            spi_dev_path: "/dev/spidev1.0".into(),
+            led_count: 12,
+        }
+    }
+}
+
+/// Drives WS2812 LEDs through the MOSI line of an SPI bus, which they cannot
+/// share with other devices.
+pub struct Ws2812Driver {
+    spi: Spidev,
+    led_count: usize,
+}
+
+impl Ws2812Driver {
+    pub fn new(config: Ws2812Config) -> Result<Self> {
+        let mut spi = Spidev::open(&config.spi_dev_path)
+            .with_context(|| format!("Failed to open {}", config.spi_dev_path.display()))?;
+        spi.configure(
+            &SpidevOptions::new()
+                .bits_per_word(8)
+                .max_speed_hz(SPI_SPEED_HZ)
+                .mode(SpiModeFlags::SPI_MODE_0)
+                .build(),
+        )?;
+
+        Ok(Self {
+            spi,
+            led_count: config.led_count,
+        })
+    }
+}
+
+impl LedDriver for Ws2812Driver {
+    fn set_color(&mut self, rgb: Rgb) -> Result<()> {
+        // WS2812 LEDs expect their channels in GRB order.
+        let pixel = encode(&[rgb.1, rgb.0, rgb.2]);
+        let mut frame = Vec::with_capacity(pixel.len() * self.led_count + RESET_BYTES);
+
+        for _ in 0..self.led_count {
+            frame.extend_from_slice(&pixel);
+        }
+
+        frame.resize(frame.len() + RESET_BYTES, 0);
+        self.spi.write_all(&frame)?;
+
+        Ok(())
+    }
+}
+
+/// Encodes each data bit as three SPI bits, `110` for a one and `100` for a
+/// zero.
+fn encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(data.len() * 3);
+
+    for byte in data {
+        let mut bits: u32 = 0;
+
+        for i in (0..8).rev() {
+            let pattern = if byte & (1 << i) != 0 { 0b110 } else { 0b100 };
+            bits = (bits << 3) | pattern;
+        }
+
+        encoded.extend_from_slice(&bits.to_be_bytes()[1..]);
+    }
+
+    encoded
+}
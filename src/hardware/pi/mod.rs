@@ -1,23 +1,22 @@
+use crate::config::load_config;
 use crate::hardware::led::LedController;
-use crate::hardware::nfc::NfcReader;
+use crate::hardware::nfc::NfcReaderHandle;
 use crate::hardware::pi::buttons::{Buttons, ButtonsConfig};
 use crate::hardware::pi::led::{start_led_controller_thread, LedControllerConfig};
+use crate::hardware::pi::nfc::{start_nfc_reader_thread, NfcReadersConfig};
 use crate::hardware::{InitSubsystems, Peripherals, StartSubsystems};
-use crate::thread::{supervised_thread, SupervisedThread};
-use anyhow::{Context, Result};
-use bloop_client_framework::nfc::serve_mfrc522;
+use crate::thread::SupervisedThread;
+use anyhow::{bail, Result};
 use serde::Deserialize;
-use std::fs::File;
-use std::io::Read;
-use std::path::PathBuf;
 use tokio::sync::mpsc;
 use tokio_graceful_shutdown::{IntoSubsystem, SubsystemBuilder, SubsystemHandle};
 use tokio_util::sync::CancellationToken;
-use tracing::info;
 
 pub mod asset;
 mod buttons;
+mod encoder;
 mod led;
+mod nfc;
 pub mod system;
 
 pub struct HardwareContext {
@@ -29,26 +28,59 @@ pub struct HardwareContext {
 pub fn init_hardware(shutdown_token: CancellationToken) -> Result<HardwareContext> {
     let (led_state_tx, led_state_rx) = mpsc::channel(32);
     let (button_tx, button_rx) = mpsc::channel(32);
-    let (nfc_reader, nfc_backend) = NfcReader::channel();
+    let (action_tx, action_rx) = mpsc::channel(32);
+    let (nfc_health_tx, nfc_health_rx) = mpsc::channel(8);
+    let config: Config = load_config()?;
+
+    let mut threads = vec![start_led_controller_thread(
+        led_state_rx,
+        shutdown_token.clone(),
+        config.led_controller,
+    )?];
+
+    let nfc_reader_configs = config.nfc_reader.into_vec();
+
+    if nfc_reader_configs.is_empty() {
+        bail!("no NFC readers configured");
+    }
+
+    let single_reader = nfc_reader_configs.len() == 1;
+    let mut nfc_readers = Vec::with_capacity(nfc_reader_configs.len());
+
+    for (index, nfc_reader_config) in nfc_reader_configs.into_iter().enumerate() {
+        let id = nfc_reader_config
+            .id
+            .clone()
+            .unwrap_or_else(|| index.to_string());
+        let thread_name = if single_reader {
+            "nfc_reader".to_string()
+        } else {
+            format!("nfc_reader_{}", id)
+        };
+        let (nfc_reader, request_rx, write_rx) = NfcReaderHandle::channel(id.clone());
+
+        threads.push(start_nfc_reader_thread(
+            thread_name,
+            id,
+            request_rx,
+            write_rx,
+            nfc_health_tx.clone(),
+            shutdown_token.clone(),
+            nfc_reader_config,
+        )?);
+        nfc_readers.push(nfc_reader);
+    }
 
     let peripherals = Peripherals {
         led_controller: LedController::new(led_state_tx),
-        nfc_reader,
+        nfc_readers,
+        nfc_health_rx,
         button_receiver: button_rx,
+        action_receiver: action_rx,
     };
 
-    let config = load_config()?;
-    let nfc_reader_config = config.nfc_reader.into();
-
-    let threads = vec![
-        start_led_controller_thread(led_state_rx, shutdown_token.clone(), config.led_controller)?,
-        supervised_thread("nfc_reader", shutdown_token, move || {
-            Ok(serve_mfrc522(nfc_reader_config, nfc_backend)?)
-        })?,
-    ];
-
     let init_subsystems = Box::new(move || -> Result<StartSubsystems> {
-        let buttons = Buttons::new(button_tx, config.buttons)?;
+        let buttons = Buttons::new(button_tx, action_tx, config.buttons)?;
 
         Ok(Box::new(move |s: &SubsystemHandle| {
             s.start(SubsystemBuilder::new("Buttons", buttons.into_subsystem()));
@@ -69,62 +101,5 @@ struct Config {
     #[serde(default)]
     led_controller: LedControllerConfig,
     #[serde(default)]
-    nfc_reader: NfcReaderConfig,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(default)]
-struct NfcReaderConfig {
-    spi_dev_path: PathBuf,
-    gpio_dev_path: PathBuf,
-    reset_pin_line: u32,
-}
-
-impl Default for NfcReaderConfig {
-    fn default() -> Self {
-        let defaults = bloop_client_framework::nfc::Mfrc522Config::default();
-
-        Self {
-            spi_dev_path: defaults.spi_dev_path,
-            gpio_dev_path: defaults.gpio_dev_path,
-            reset_pin_line: defaults.reset_pin_line,
-        }
-    }
-}
-
-impl From<NfcReaderConfig> for bloop_client_framework::nfc::Mfrc522Config {
-    fn from(config: NfcReaderConfig) -> Self {
-        let mut framework_config = Self::default();
-        framework_config.spi_dev_path = config.spi_dev_path;
-        framework_config.gpio_dev_path = config.gpio_dev_path;
-        framework_config.reset_pin_line = config.reset_pin_line;
-
-        framework_config
-    }
-}
-
-fn load_config() -> Result<Config> {
-    let path: PathBuf = "/etc/bloop-box.conf".into();
-
-    let mut file = match File::open(&path) {
-        Ok(file) => file,
-        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
-            info!(
-                "Config file {} not found, using default config",
-                path.display()
-            );
-            return Ok(Config::default());
-        }
-        Err(err) => {
-            return Err(err).with_context(|| format!("Failed to open {}", path.display()))?
-        }
-    };
-
-    let mut toml_config = String::new();
-    file.read_to_string(&mut toml_config)
-        .with_context(|| format!("Failed to read {}", path.display()))?;
-    let config: Config = toml::from_str(&toml_config)
-        .with_context(|| format!("Failed to parse {}", path.display()))?;
-
-    Ok(config)
+    nfc_reader: NfcReadersConfig,
 }
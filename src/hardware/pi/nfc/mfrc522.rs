@@ -0,0 +1,271 @@
+use crate::hardware::pi::nfc::{crc_a, NfcDriver};
+use anyhow::{bail, Context, Result};
+use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+use gpiocdev::line::Value;
+use gpiocdev::Request;
+use linux_embedded_hal::spidev::{SpiModeFlags, Spidev, SpidevOptions};
+use linux_embedded_hal::{SPIError, SpidevDevice};
+use mfrc522::comm::blocking::spi::{DummyDelay, SpiInterface};
+use mfrc522::{Initialized, Mfrc522};
+use serde::Deserialize;
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+const SPI_SPEED_HZ: u32 = 1_000_000;
+
+const TX_CONTROL_REG: u8 = 0x14;
+const T_PRESCALER_REG: u8 = 0x2b;
+const VERSION_REG: u8 = 0x37;
+
+/// Antenna driver bits of the TxControlReg, which are cleared when the chip
+/// resets itself.
+const TX_ANTENNA_ON: u8 = 0x03;
+
+/// Prescaler of the receive timer as set up by the driver, which is cleared
+/// when the chip resets itself.
+const T_PRESCALER: u8 = 0xa9;
+
+/// Size of the FIFO of the chip, which holds the response of a card.
+const FIFO_SIZE: usize = 64;
+
+/// Longer than any command takes with the 25 ms receive timeout of the
+/// chip's own timer.
+const COMMAND_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Mfrc522Config {
+    spi_dev_path: PathBuf,
+    gpio_dev_path: PathBuf,
+    reset_pin_line: u32,
+}
+
+impl Default for Mfrc522Config {
+    fn default() -> Self {
+        Self {
+            spi_dev_path: "/dev/spidev0.0".into(),
+            gpio_dev_path: "/dev/gpiochip0".into(),
+            reset_pin_line: 25,
+        }
+    }
+}
+
+/// SPI device of the chip, shared by the chip driver and the health checks.
+///
+/// The chip driver waits for the chip without a timeout of its own, which
+/// would block the reader thread for good once the chip lost its timer setup,
+/// so transfers fail once the running command exceeds its deadline.
+#[derive(Clone)]
+struct SharedSpi(Rc<SharedSpiInner>);
+
+struct SharedSpiInner {
+    device: RefCell<SpidevDevice>,
+    deadline: Cell<Option<Instant>>,
+}
+
+impl SharedSpi {
+    /// Runs a command of the chip driver, which fails once it takes longer
+    /// than [`COMMAND_TIMEOUT`].
+    fn with_deadline<T>(&self, command: impl FnOnce() -> T) -> T {
+        self.0.deadline.set(Some(Instant::now() + COMMAND_TIMEOUT));
+        let result = command();
+        self.0.deadline.set(None);
+
+        result
+    }
+
+    fn read_register(&mut self, register: u8) -> Result<u8> {
+        let mut buffer = [0x80 | (register << 1), 0];
+        self.transaction(&mut [Operation::TransferInPlace(&mut buffer)])?;
+
+        Ok(buffer[1])
+    }
+}
+
+impl ErrorType for SharedSpi {
+    type Error = SPIError;
+}
+
+impl SpiDevice for SharedSpi {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), SPIError> {
+        if self
+            .0
+            .deadline
+            .get()
+            .is_some_and(|deadline| deadline < Instant::now())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "MFRC522 did not complete the command",
+            )
+            .into());
+        }
+
+        self.0.device.borrow_mut().transaction(operations)
+    }
+}
+
+type Chip = Mfrc522<SpiInterface<SharedSpi, DummyDelay>, Initialized>;
+
+/// Reads ISO 14443A cards through an MFRC522 on SPI.
+///
+/// Selection is left to the `mfrc522` crate, only the Type 2 tag commands
+/// are sent as raw frames.
+pub struct Mfrc522Driver {
+    spi: SharedSpi,
+    /// `None` while the chip could not be set up.
+    chip: Option<Chip>,
+    /// Whether the last selection found a card, which is then active.
+    selected: bool,
+    /// Keeps the reset line driven high while the driver is in use.
+    reset: Request,
+    reset_pin_line: u32,
+}
+
+impl Mfrc522Driver {
+    pub fn new(config: Mfrc522Config) -> Result<Self> {
+        let mut spi = Spidev::open(&config.spi_dev_path)
+            .with_context(|| format!("Failed to open {}", config.spi_dev_path.display()))?;
+        spi.configure(
+            &SpidevOptions::new()
+                .bits_per_word(8)
+                .max_speed_hz(SPI_SPEED_HZ)
+                .mode(SpiModeFlags::SPI_MODE_0)
+                .build(),
+        )?;
+
+        let reset = Request::builder()
+            .on_chip(config.gpio_dev_path)
+            .with_consumer("bloop-box")
+            .with_line(config.reset_pin_line)
+            .as_output(Value::Inactive)
+            .request()
+            .context("Failed to create GPIO request")?;
+
+        let mut driver = Self {
+            spi: SharedSpi(Rc::new(SharedSpiInner {
+                device: RefCell::new(SpidevDevice(spi)),
+                deadline: Cell::new(None),
+            })),
+            chip: None,
+            selected: false,
+            reset,
+            reset_pin_line: config.reset_pin_line,
+        };
+        driver.reset()?;
+
+        Ok(driver)
+    }
+}
+
+/// Splits errors of the chip driver into failures of the reader and cards
+/// not answering as expected, for which `None` is returned.
+fn card_response<T>(result: Result<T, mfrc522::Error<SPIError>>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(mfrc522::Error::Comm(error)) => Err(error).context("MFRC522 communication failed"),
+        Err(mfrc522::Error::Timeout) => Ok(None),
+        Err(error) => {
+            debug!("MFRC522 card error {:?}", error);
+            Ok(None)
+        }
+    }
+}
+
+impl NfcDriver for Mfrc522Driver {
+    fn check_health(&mut self) -> Result<()> {
+        if self.chip.is_none() {
+            bail!("MFRC522 is not set up");
+        }
+
+        let version = self.spi.read_register(VERSION_REG)?;
+
+        if version == 0x00 || version == 0xff {
+            bail!("MFRC522 does not respond");
+        }
+
+        if self.spi.read_register(TX_CONTROL_REG)? & TX_ANTENNA_ON != TX_ANTENNA_ON
+            || self.spi.read_register(T_PRESCALER_REG)? != T_PRESCALER
+        {
+            bail!("MFRC522 lost its configuration");
+        }
+
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.chip = None;
+        self.selected = false;
+
+        self.reset.set_value(self.reset_pin_line, Value::Inactive)?;
+        sleep(Duration::from_millis(10));
+        self.reset.set_value(self.reset_pin_line, Value::Active)?;
+        // Gives the oscillator time to start up.
+        sleep(Duration::from_millis(50));
+
+        let chip = Mfrc522::new(SpiInterface::new(self.spi.clone()));
+        let chip = self
+            .spi
+            .with_deadline(|| chip.init())
+            .map_err(|error| anyhow::anyhow!("Failed to set up MFRC522: {:?}", error))?;
+        self.chip = Some(chip);
+
+        Ok(())
+    }
+
+    fn select(&mut self) -> Result<Option<Vec<u8>>> {
+        let halt = mem::take(&mut self.selected);
+        let chip = self.chip.as_mut().context("MFRC522 is not set up")?;
+
+        let uid = card_response(self.spi.with_deadline(|| {
+            // A card selected before only answers the wake-up once halted, so
+            // presence checks work while it stays in the field. Halting is
+            // not acknowledged, so only a failing reader is an error.
+            if halt {
+                if let Err(mfrc522::Error::Comm(error)) = chip.hlta() {
+                    return Err(mfrc522::Error::Comm(error));
+                }
+            }
+
+            let atqa = chip.wupa()?;
+            chip.select(&atqa)
+        }))?;
+
+        self.selected = uid.is_some();
+        Ok(uid.map(|uid| uid.as_bytes().to_vec()))
+    }
+
+    fn transceive(&mut self, command: &[u8]) -> Result<Option<Vec<u8>>> {
+        let chip = self.chip.as_mut().context("MFRC522 is not set up")?;
+        let mut frame = command.to_vec();
+        frame.extend_from_slice(&crc_a(&frame));
+
+        let Some(response) = card_response(
+            self.spi
+                .with_deadline(|| chip.transceive::<FIFO_SIZE>(&frame, 0, 0)),
+        )?
+        else {
+            return Ok(None);
+        };
+
+        let data = &response.buffer[..response.valid_bytes];
+
+        match (data, response.valid_bits) {
+            // Acknowledgements of writes only have four bits.
+            ([ack], 4) => Ok(Some(vec![*ack])),
+            ([data @ .., crc_low, crc_high], 0) if crc_a(data) == [*crc_low, *crc_high] => {
+                Ok(Some(data.to_vec()))
+            }
+            _ => {
+                debug!("malformed card response {}", hex::encode(data));
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Scripted transport and simulated tags for testing reader drivers without
+//! hardware.
+
+use crate::hardware::pi::nfc::pn532::Transport;
+use crate::hardware::pi::nfc::NfcDriver;
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::thread;
+
+/// Answers commands with scripted responses, in order. Every command has to
+/// match the one expected next, and all of them have to be sent by the time
+/// the transport is dropped.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    exchanges: VecDeque<(Vec<u8>, Vec<u8>)>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects the command next and answers it with the response.
+    pub fn expect(mut self, command: &[u8], response: &[u8]) -> Self {
+        self.exchanges
+            .push_back((command.to_vec(), response.to_vec()));
+        self
+    }
+}
+
+impl Transport for MockTransport {
+    fn exchange(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+        let (expected, response) = self
+            .exchanges
+            .pop_front()
+            .unwrap_or_else(|| panic!("unexpected command {}", hex::encode(command)));
+
+        assert_eq!(hex::encode(command), hex::encode(expected));
+        Ok(response)
+    }
+}
+
+impl Drop for MockTransport {
+    fn drop(&mut self) {
+        if !thread::panicking() {
+            assert!(
+                self.exchanges.is_empty(),
+                "{} expected commands were not sent",
+                self.exchanges.len()
+            );
+        }
+    }
+}
+
+/// Type 2 tag answering reads and writes of its pages.
+#[derive(Debug)]
+pub struct MockTag {
+    pub memory: Vec<u8>,
+}
+
+impl MockTag {
+    /// Creates an NDEF formatted tag with the given data area.
+    pub fn new(data_area: &[u8]) -> Self {
+        let mut memory = vec![0; 12];
+        memory.extend_from_slice(&[0xe1, 0x10, (data_area.len() / 8) as u8, 0x00]);
+        memory.extend_from_slice(data_area);
+
+        Self { memory }
+    }
+}
+
+impl NfcDriver for MockTag {
+    fn check_health(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn select(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(Some(vec![0x04, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06]))
+    }
+
+    fn transceive(&mut self, command: &[u8]) -> Result<Option<Vec<u8>>> {
+        let offset = command[1] as usize * 4;
+
+        match command[0] {
+            0x30 => Ok(self
+                .memory
+                .get(offset..offset + 16)
+                .map(|pages| pages.to_vec())),
+            0xa2 => match self.memory.get_mut(offset..offset + 4) {
+                Some(page) => {
+                    page.copy_from_slice(&command[2..6]);
+                    Ok(Some(vec![0x0a]))
+                }
+                None => Ok(Some(vec![0x00])),
+            },
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use crate::hardware::nfc::{NfcReaderRequest, NfcUid, NfcWriteRequest, ReaderHealth};
+use crate::hardware::pi::nfc::mfrc522::{Mfrc522Config, Mfrc522Driver};
+use crate::hardware::pi::nfc::pcsc::PcscConfig;
+use crate::hardware::pi::nfc::pn532::Pn532Config;
+use crate::ndef::{self, ConfigRecord};
+use crate::thread::{supervised_thread, SupervisedThread};
+use anyhow::{Context, Result};
+use hex::FromHex;
+use serde::Deserialize;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
+
+mod mfrc522;
+#[cfg(test)]
+mod mock;
+mod pcsc;
+mod pn532;
+mod type2;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct NfcReaderConfig {
+    /// ID the reader is referred to by in logs, defaults to its position.
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    driver: DriverKind,
+    /// The MFRC522 options live in the section itself for compatibility with
+    /// older config files.
+    #[serde(flatten)]
+    mfrc522: Mfrc522Config,
+    #[serde(default)]
+    pn532: Pn532Config,
+    #[serde(default)]
+    pcsc: PcscConfig,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DriverKind {
+    #[default]
+    Mfrc522,
+    Pn532,
+    Pcsc,
+}
+
+/// Either a single `[nfc_reader]` table or a list of `[[nfc_reader]]` tables.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum NfcReadersConfig {
+    Single(NfcReaderConfig),
+    Multiple(Vec<NfcReaderConfig>),
+}
+
+impl Default for NfcReadersConfig {
+    fn default() -> Self {
+        Self::Single(NfcReaderConfig::default())
+    }
+}
+
+impl NfcReadersConfig {
+    pub fn into_vec(self) -> Vec<NfcReaderConfig> {
+        match self {
+            Self::Single(config) => vec![config],
+            Self::Multiple(configs) => configs,
+        }
+    }
+}
+
+/// Access to cards through a reader.
+trait NfcDriver {
+    /// Checks whether the reader still responds and is set up.
+    fn check_health(&mut self) -> Result<()>;
+
+    /// Resets the reader and sets it up again.
+    fn reset(&mut self) -> Result<()>;
+
+    /// Looks for a card in the field and selects it, returning its UID.
+    fn select(&mut self) -> Result<Option<Vec<u8>>>;
+
+    /// Sends a command to the selected card, returning its response or
+    /// `None` if the card did not answer.
+    fn transceive(&mut self, command: &[u8]) -> Result<Option<Vec<u8>>>;
+}
+
+/// Interval at which the field is checked for cards.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Number of checks in a row a card has to be missing before it counts as
+/// removed.
+const REMOVAL_MISSES: u32 = 2;
+
+/// Interval at which the reader is checked while it is healthy.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Interval at which resets are attempted while the reader is unresponsive.
+const RESET_INTERVAL: Duration = Duration::from_secs(2);
+
+enum Request {
+    Read(NfcReaderRequest),
+    Write(NfcWriteRequest),
+}
+
+/// Checks the reader periodically and resets it while it is unresponsive,
+/// reporting changes of its health.
+struct HealthMonitor {
+    reader_id: String,
+    health_tx: mpsc::Sender<ReaderHealth>,
+    healthy: bool,
+    /// Time of the last check, `None` if one is due right away.
+    last_check: Option<Instant>,
+}
+
+impl HealthMonitor {
+    /// Checks the reader if a check is due, returning whether it is healthy.
+    fn check(&mut self, driver: &mut dyn NfcDriver) -> bool {
+        let interval = if self.healthy {
+            HEALTH_CHECK_INTERVAL
+        } else {
+            RESET_INTERVAL
+        };
+
+        if self
+            .last_check
+            .is_some_and(|last_check| last_check.elapsed() < interval)
+        {
+            return self.healthy;
+        }
+
+        self.last_check = Some(Instant::now());
+
+        let result = if self.healthy {
+            driver.check_health()
+        } else {
+            driver.reset().and_then(|()| driver.check_health())
+        };
+
+        match result {
+            Ok(()) => self.set_healthy(true),
+            Err(error) if self.healthy => self.fail(error),
+            Err(error) => debug!(
+                "NFC reader {} still unresponsive: {:#}",
+                self.reader_id, error
+            ),
+        }
+
+        self.healthy
+    }
+
+    /// Marks the reader as unresponsive, so it is reset right away.
+    fn fail(&mut self, error: anyhow::Error) {
+        if self.healthy {
+            error!("NFC reader {} is unresponsive: {:#}", self.reader_id, error);
+        }
+
+        self.last_check = None;
+        self.set_healthy(false);
+    }
+
+    fn set_healthy(&mut self, healthy: bool) {
+        if self.healthy == healthy {
+            return;
+        }
+
+        if healthy {
+            info!("NFC reader {} recovered", self.reader_id);
+        }
+
+        self.healthy = healthy;
+        let _ = self.health_tx.blocking_send(ReaderHealth {
+            reader_id: self.reader_id.clone(),
+            healthy,
+        });
+    }
+}
+
+pub fn start_nfc_reader_thread(
+    name: String,
+    reader_id: String,
+    request_rx: mpsc::Receiver<NfcReaderRequest>,
+    write_rx: mpsc::Receiver<NfcWriteRequest>,
+    health_tx: mpsc::Sender<ReaderHealth>,
+    shutdown_token: CancellationToken,
+    config: NfcReaderConfig,
+) -> Result<SupervisedThread> {
+    let monitor = HealthMonitor {
+        reader_id,
+        health_tx,
+        healthy: true,
+        last_check: Some(Instant::now()),
+    };
+
+    Ok(supervised_thread(name, shutdown_token, move || {
+        nfc_reader_thread(request_rx, write_rx, monitor, config)
+    })?)
+}
+
+fn nfc_reader_thread(
+    mut request_rx: mpsc::Receiver<NfcReaderRequest>,
+    mut write_rx: mpsc::Receiver<NfcWriteRequest>,
+    mut monitor: HealthMonitor,
+    config: NfcReaderConfig,
+) -> Result<()> {
+    let mut driver: Box<dyn NfcDriver> = match config.driver {
+        DriverKind::Mfrc522 => Box::new(Mfrc522Driver::new(config.mfrc522)?),
+        DriverKind::Pn532 => pn532::open(config.pn532)?,
+        DriverKind::Pcsc => pcsc::open(config.pcsc)?,
+    };
+    let driver = driver.as_mut();
+    let mut current_uid = None;
+
+    // Only used to wait for requests on both channels, with a timeout for
+    // health checks in between.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()?;
+
+    loop {
+        let request = runtime.block_on(timeout(HEALTH_CHECK_INTERVAL, async {
+            select! {
+                Some(request) = request_rx.recv() => Some(Request::Read(request)),
+                Some(request) = write_rx.recv() => Some(Request::Write(request)),
+                else => None,
+            }
+        }));
+
+        let request =
+            match request {
+                Ok(Some(Request::Read(request))) => request,
+                Ok(Some(Request::Write(request))) => {
+                    let _ = request.response.send(
+                        write_config_record(driver, &request.record).map_err(|error| {
+                            warn!("failed to write card: {:#}", error);
+                            error.to_string()
+                        }),
+                    );
+                    continue;
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    monitor.check(driver);
+                    continue;
+                }
+            };
+
+        match request {
+            NfcReaderRequest::WaitForCard(response) => loop {
+                if response.is_closed() {
+                    break;
+                }
+
+                if monitor.check(driver) {
+                    match driver.select() {
+                        Ok(Some(uid)) => match nfc_uid(&uid) {
+                            Some(nfc_uid) => {
+                                current_uid = Some(uid);
+                                let _ = response.send(nfc_uid);
+                                break;
+                            }
+                            None => warn!("unsupported UID {}", hex::encode(&uid)),
+                        },
+                        Ok(None) => {}
+                        Err(error) => monitor.fail(error),
+                    }
+                }
+
+                sleep(POLL_INTERVAL);
+            },
+
+            NfcReaderRequest::WaitForRemoval(response) => {
+                let mut misses = 0;
+
+                while current_uid.is_some() && !response.is_closed() {
+                    let uid = driver.select().unwrap_or_else(|error| {
+                        monitor.fail(error);
+                        None
+                    });
+
+                    if uid == current_uid {
+                        misses = 0;
+                    } else if uid.is_some() {
+                        // A different card means the previous one is gone, so
+                        // the next one can be handled right away.
+                        current_uid = None;
+                    } else {
+                        misses += 1;
+
+                        if misses >= REMOVAL_MISSES {
+                            current_uid = None;
+                        }
+                    }
+
+                    sleep(POLL_INTERVAL);
+                }
+
+                let _ = response.send(());
+            }
+
+            NfcReaderRequest::ReadNdefText(response) => {
+                let _ = response.send(read_config_payload(driver).map_err(|error| {
+                    warn!("failed to read card: {:#}", error);
+                    error.to_string()
+                }));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the record to the card in the field, which has to be blank.
+fn write_config_record(driver: &mut dyn NfcDriver, record: &ConfigRecord) -> Result<()> {
+    driver.select()?.context("no card present")?;
+    type2::write_message(driver, &ndef::encode_message(&[record.to_record()]))
+}
+
+/// Reads the config payload from the first record of the selected card
+/// which carries one.
+fn read_config_payload(driver: &mut dyn NfcDriver) -> Result<String> {
+    let message = type2::read_message(driver)?;
+    let records = ndef::parse_message(&message)?;
+
+    ndef::config_payload(&records).context("card carries no config record")
+}
+
+fn nfc_uid(uid: &[u8]) -> Option<NfcUid> {
+    NfcUid::from_hex(hex::encode(uid)).ok()
+}
+
+/// Computes the CRC_A of ISO 14443-3, in transmission order.
+fn crc_a(data: &[u8]) -> [u8; 2] {
+    let mut crc: u16 = 0x6363;
+
+    for &byte in data {
+        let mut byte = byte ^ crc as u8;
+        byte ^= byte << 4;
+        crc = (crc >> 8) ^ ((byte as u16) << 8) ^ ((byte as u16) << 3) ^ ((byte as u16) >> 4);
+    }
+
+    crc.to_le_bytes()
+}
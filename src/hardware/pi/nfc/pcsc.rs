@@ -0,0 +1,131 @@
+//! ACR122U readers on PC/SC, which contain a PN532 reachable through escape
+//! commands.
+//!
+//! The reader is connected in direct mode, so it can be reached without a card
+//! in the field. With the CCID driver this requires escape commands to be
+//! enabled through `ifdDriverOptions` in its `Info.plist`.
+
+#[cfg(feature = "pcsc")]
+use crate::hardware::pi::nfc::pn532::{Pn532Driver, Transport};
+use crate::hardware::pi::nfc::NfcDriver;
+#[cfg(feature = "pcsc")]
+use anyhow::Context;
+use anyhow::{bail, Result};
+#[cfg(feature = "pcsc")]
+use pcsc::{Card, Protocols, Scope, ShareMode};
+use serde::Deserialize;
+
+/// Pseudo APDU passing a command on to the PN532.
+const DIRECT_TRANSMIT: [u8; 4] = [0xff, 0x00, 0x00, 0x00];
+
+/// Function code of escape commands, which differs between the CCID driver of
+/// pcsc-lite and the one of Windows.
+#[cfg(all(feature = "pcsc", not(windows)))]
+const IOCTL_CCID_ESCAPE: u32 = 1;
+
+#[cfg(all(feature = "pcsc", windows))]
+const IOCTL_CCID_ESCAPE: u32 = 3500;
+
+const STATUS_SUCCESS: [u8; 2] = [0x90, 0x00];
+
+#[cfg(feature = "pcsc")]
+const MAX_RESPONSE_LEN: usize = 264;
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct PcscConfig {
+    /// Name of the reader, defaults to the first one found.
+    #[cfg_attr(not(feature = "pcsc"), allow(dead_code))]
+    reader: Option<String>,
+}
+
+/// Opens the configured PC/SC reader.
+#[cfg(feature = "pcsc")]
+pub fn open(config: PcscConfig) -> Result<Box<dyn NfcDriver>> {
+    Ok(Box::new(Pn532Driver::new(PcscTransport::new(config)?)?))
+}
+
+#[cfg(not(feature = "pcsc"))]
+pub fn open(_config: PcscConfig) -> Result<Box<dyn NfcDriver>> {
+    bail!("PC/SC readers require building with the pcsc feature")
+}
+
+#[cfg(feature = "pcsc")]
+struct PcscTransport {
+    card: Card,
+}
+
+#[cfg(feature = "pcsc")]
+impl PcscTransport {
+    fn new(config: PcscConfig) -> Result<Self> {
+        let context = pcsc::Context::establish(Scope::User)?;
+        let readers = context.list_readers_owned()?;
+
+        let reader = match &config.reader {
+            Some(name) => readers
+                .iter()
+                .find(|reader| reader.to_string_lossy() == *name)
+                .with_context(|| format!("PC/SC reader {name} not found"))?,
+            None => readers.first().context("no PC/SC reader found")?,
+        };
+
+        let card = context
+            .connect(reader, ShareMode::Direct, Protocols::UNDEFINED)
+            .with_context(|| format!("Failed to connect to {}", reader.to_string_lossy()))?;
+
+        Ok(Self { card })
+    }
+}
+
+#[cfg(feature = "pcsc")]
+impl Transport for PcscTransport {
+    fn exchange(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+        let mut buffer = [0; MAX_RESPONSE_LEN];
+        let response = self.card.control(
+            pcsc::ctl_code(IOCTL_CCID_ESCAPE.into()),
+            &direct_transmit(command),
+            &mut buffer,
+        )?;
+
+        response_data(response)
+    }
+}
+
+#[cfg_attr(not(feature = "pcsc"), allow(dead_code))]
+fn direct_transmit(command: &[u8]) -> Vec<u8> {
+    let mut apdu = DIRECT_TRANSMIT.to_vec();
+    apdu.push(command.len() as u8);
+    apdu.extend_from_slice(command);
+
+    apdu
+}
+
+#[cfg_attr(not(feature = "pcsc"), allow(dead_code))]
+fn response_data(response: &[u8]) -> Result<Vec<u8>> {
+    match response.strip_suffix(&STATUS_SUCCESS) {
+        Some(data) => Ok(data.to_vec()),
+        None => bail!("reader rejected command: {}", hex::encode(response)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_commands() {
+        assert_eq!(
+            direct_transmit(&[0xd4, 0x02]),
+            [0xff, 0x00, 0x00, 0x00, 0x02, 0xd4, 0x02]
+        );
+    }
+
+    #[test]
+    fn unwraps_responses() {
+        assert_eq!(
+            response_data(&[0xd5, 0x03, 0x32, 0x01, 0x06, 0x07, 0x90, 0x00]).unwrap(),
+            [0xd5, 0x03, 0x32, 0x01, 0x06, 0x07]
+        );
+        assert!(response_data(&[0x63, 0x00]).is_err());
+    }
+}
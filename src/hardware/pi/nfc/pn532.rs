@@ -0,0 +1,494 @@
+//! PN532 readers on I2C or UART.
+//!
+//! The PN532 is driven through its command set, which is framed the same way
+//! on every interface. ACR122U readers on PC/SC contain a PN532 as well and
+//! reuse the driver with their own transport.
+
+use crate::hardware::pi::nfc::NfcDriver;
+use anyhow::{bail, Context, Result};
+use linux_embedded_hal::i2cdev::core::I2CDevice;
+use linux_embedded_hal::i2cdev::linux::LinuxI2CDevice;
+use serde::Deserialize;
+use serialport::SerialPort;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+const HOST_TO_PN532: u8 = 0xd4;
+const PN532_TO_HOST: u8 = 0xd5;
+
+const COMMAND_GET_FIRMWARE_VERSION: u8 = 0x02;
+const COMMAND_SAM_CONFIGURATION: u8 = 0x14;
+const COMMAND_RF_CONFIGURATION: u8 = 0x32;
+const COMMAND_IN_DATA_EXCHANGE: u8 = 0x40;
+const COMMAND_IN_LIST_PASSIVE_TARGET: u8 = 0x4a;
+
+const IC_PN532: u8 = 0x32;
+const BAUD_RATE_106_TYPE_A: u8 = 0x00;
+const TARGET: u8 = 0x01;
+
+const STATUS_ERROR_MASK: u8 = 0x3f;
+const STATUS_TIMEOUT: u8 = 0x01;
+
+/// Answer of Type 2 tags to writes.
+const ACK: u8 = 0x0a;
+
+const I2C_ADDRESS: u16 = 0x24;
+const I2C_READY: u8 = 0x01;
+const UART_BAUD_RATE: u32 = 115_200;
+
+/// Wakes the PN532 up on UART, sent ahead of the first command.
+const UART_WAKE_UP: [u8; 16] = [0x55, 0x55, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+const ACK_FRAME_LEN: usize = 6;
+
+/// Longest frame read, which leaves room for the ATS of ISO 14443-4 cards.
+const MAX_FRAME_LEN: usize = 64;
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Interface {
+    #[default]
+    I2c,
+    Uart,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Pn532Config {
+    interface: Interface,
+    i2c_dev_path: PathBuf,
+    uart_dev_path: PathBuf,
+}
+
+impl Default for Pn532Config {
+    fn default() -> Self {
+        Self {
+            interface: Interface::default(),
+            i2c_dev_path: "/dev/i2c-1".into(),
+            uart_dev_path: "/dev/serial0".into(),
+        }
+    }
+}
+
+/// Opens the PN532 on the configured interface.
+pub fn open(config: Pn532Config) -> Result<Box<dyn NfcDriver>> {
+    Ok(match config.interface {
+        Interface::I2c => Box::new(Pn532Driver::new(I2cTransport::new(&config.i2c_dev_path)?)?),
+        Interface::Uart => Box::new(Pn532Driver::new(UartTransport::new(
+            &config.uart_dev_path,
+        )?)?),
+    })
+}
+
+/// Link to a PN532, exchanging commands for their responses.
+pub trait Transport {
+    /// Sends a command, starting with the frame identifier, and returns the
+    /// response in the same form.
+    fn exchange(&mut self, command: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Reads ISO 14443A cards through a PN532.
+pub struct Pn532Driver<T> {
+    transport: T,
+}
+
+impl<T: Transport> Pn532Driver<T> {
+    pub fn new(transport: T) -> Result<Self> {
+        let mut driver = Self { transport };
+        driver.setup()?;
+
+        Ok(driver)
+    }
+
+    fn setup(&mut self) -> Result<()> {
+        let (version, revision) = self.firmware_version()?;
+        debug!("PN532 firmware {}.{}", version, revision);
+
+        // Normal mode, without a secure access module.
+        self.command(COMMAND_SAM_CONFIGURATION, &[0x01, 0x14, 0x01])?;
+        // A single activation attempt, so polling returns right away when there
+        // is no card in the field.
+        self.command(COMMAND_RF_CONFIGURATION, &[0x05, 0xff, 0x01, 0x01])?;
+
+        Ok(())
+    }
+
+    fn firmware_version(&mut self) -> Result<(u8, u8)> {
+        match self.command(COMMAND_GET_FIRMWARE_VERSION, &[])?[..] {
+            [IC_PN532, version, revision, ..] => Ok((version, revision)),
+            _ => bail!("PN532 not found"),
+        }
+    }
+
+    /// Sends a command and returns the parameters of its response.
+    fn command(&mut self, command: u8, parameters: &[u8]) -> Result<Vec<u8>> {
+        let mut frame = vec![HOST_TO_PN532, command];
+        frame.extend_from_slice(parameters);
+
+        match &self.transport.exchange(&frame)?[..] {
+            [PN532_TO_HOST, response, parameters @ ..] if *response == command + 1 => {
+                Ok(parameters.to_vec())
+            }
+            _ => bail!("unexpected PN532 response to command {:#04x}", command),
+        }
+    }
+}
+
+impl<T: Transport> NfcDriver for Pn532Driver<T> {
+    fn check_health(&mut self) -> Result<()> {
+        self.firmware_version()?;
+        Ok(())
+    }
+
+    /// Sets the PN532 up again, as it has no reset line of its own.
+    fn reset(&mut self) -> Result<()> {
+        self.setup()
+    }
+
+    fn select(&mut self) -> Result<Option<Vec<u8>>> {
+        let response = self.command(COMMAND_IN_LIST_PASSIVE_TARGET, &[1, BAUD_RATE_106_TYPE_A])?;
+
+        // Target count, followed by the target number, SENS_RES, SEL_RES, UID
+        // length and UID of the first target.
+        match response[..] {
+            [0, ..] => Ok(None),
+            [_, _, _, _, _, uid_length, ref uid @ ..] if uid.len() >= uid_length as usize => {
+                Ok(Some(uid[..uid_length as usize].to_vec()))
+            }
+            _ => bail!("invalid PN532 target data"),
+        }
+    }
+
+    fn transceive(&mut self, command: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut parameters = vec![TARGET];
+        parameters.extend_from_slice(command);
+        let response = self.command(COMMAND_IN_DATA_EXCHANGE, &parameters)?;
+        let (status, data) = response.split_first().context("missing PN532 status")?;
+
+        match status & STATUS_ERROR_MASK {
+            // The PN532 consumes the ACK of writes and reports them through
+            // its status, so it is handed on like other readers do.
+            0 if data.is_empty() => Ok(Some(vec![ACK])),
+            0 => Ok(Some(data.to_vec())),
+            STATUS_TIMEOUT => Ok(None),
+            error => bail!("PN532 error {:#04x}", error),
+        }
+    }
+}
+
+/// Frame read from the PN532.
+#[derive(Debug, PartialEq, Eq)]
+enum Frame {
+    Ack,
+    Data(Vec<u8>),
+}
+
+fn encode_frame(data: &[u8]) -> Vec<u8> {
+    let length = data.len() as u8;
+    let mut frame = vec![0x00, 0x00, 0xff, length, length.wrapping_neg()];
+    frame.extend_from_slice(data);
+    frame.push(
+        data.iter()
+            .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+            .wrapping_neg(),
+    );
+    frame.push(0x00);
+
+    frame
+}
+
+/// Decodes the frame starting at the first start code, ignoring any bytes
+/// around it.
+fn decode_frame(bytes: &[u8]) -> Result<Frame> {
+    let start = bytes
+        .windows(2)
+        .position(|window| window == [0x00, 0xff])
+        .context("missing PN532 frame start")?
+        + 2;
+
+    let (length, length_checksum) = match bytes[start..] {
+        [0x00, 0xff, ..] => return Ok(Frame::Ack),
+        [length, length_checksum, ..] => (length, length_checksum),
+        _ => bail!("truncated PN532 frame"),
+    };
+
+    if length.wrapping_add(length_checksum) != 0 {
+        bail!("invalid PN532 frame length");
+    }
+
+    let data = bytes
+        .get(start + 2..start + 3 + length as usize)
+        .context("truncated PN532 frame")?;
+
+    if data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+        bail!("invalid PN532 frame checksum");
+    }
+
+    Ok(Frame::Data(data[..length as usize].to_vec()))
+}
+
+/// Reads a single frame from a byte stream, skipping anything ahead of it.
+fn read_stream_frame(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut frame = Vec::new();
+    let mut byte = [0];
+
+    while !frame.ends_with(&[0x00, 0xff]) {
+        if frame.len() > MAX_FRAME_LEN {
+            bail!("missing PN532 frame start");
+        }
+
+        reader.read_exact(&mut byte)?;
+        frame.push(byte[0]);
+    }
+
+    let mut header = [0; 2];
+    reader.read_exact(&mut header)?;
+    frame.extend_from_slice(&header);
+
+    // The rest is the data, checksum and postamble, or just the postamble of
+    // an ACK.
+    let mut rest = match header {
+        [0x00, 0xff] => vec![0; 1],
+        [length, _] => vec![0; length as usize + 2],
+    };
+    reader.read_exact(&mut rest)?;
+    frame.extend_from_slice(&rest);
+
+    Ok(frame)
+}
+
+/// Interface which frames are written to and read from as they are.
+trait FrameLink {
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()>;
+
+    /// Reads a frame, which takes up to the given number of bytes.
+    fn read_frame(&mut self, length: usize) -> Result<Vec<u8>>;
+}
+
+fn exchange_frames(link: &mut impl FrameLink, command: &[u8]) -> Result<Vec<u8>> {
+    link.write_frame(&encode_frame(command))?;
+
+    if decode_frame(&link.read_frame(ACK_FRAME_LEN)?)? != Frame::Ack {
+        bail!("PN532 did not acknowledge the command");
+    }
+
+    match decode_frame(&link.read_frame(MAX_FRAME_LEN)?)? {
+        Frame::Data(data) => Ok(data),
+        Frame::Ack => bail!("PN532 sent no response"),
+    }
+}
+
+struct I2cTransport {
+    device: LinuxI2CDevice,
+}
+
+impl I2cTransport {
+    fn new(path: &Path) -> Result<Self> {
+        let device = LinuxI2CDevice::new(path, I2C_ADDRESS)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        Ok(Self { device })
+    }
+}
+
+impl FrameLink for I2cTransport {
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        Ok(self.device.write(frame)?)
+    }
+
+    /// Waits for the PN532 to be ready and reads the bytes following its
+    /// status byte.
+    fn read_frame(&mut self, length: usize) -> Result<Vec<u8>> {
+        let deadline = Instant::now() + RESPONSE_TIMEOUT;
+        let mut buffer = vec![0; length + 1];
+
+        loop {
+            self.device.read(&mut buffer)?;
+
+            if buffer[0] & I2C_READY != 0 {
+                return Ok(buffer.split_off(1));
+            }
+
+            if Instant::now() >= deadline {
+                bail!("PN532 did not respond");
+            }
+
+            sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+impl Transport for I2cTransport {
+    fn exchange(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+        exchange_frames(self, command)
+    }
+}
+
+struct UartTransport {
+    port: Box<dyn SerialPort>,
+    awake: bool,
+}
+
+impl UartTransport {
+    fn new(path: &Path) -> Result<Self> {
+        let port = serialport::new(path.to_string_lossy(), UART_BAUD_RATE)
+            .timeout(RESPONSE_TIMEOUT)
+            .open()
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        Ok(Self { port, awake: false })
+    }
+}
+
+impl FrameLink for UartTransport {
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        if !self.awake {
+            self.port.write_all(&UART_WAKE_UP)?;
+            self.awake = true;
+        }
+
+        self.port.write_all(frame)?;
+        Ok(self.port.flush()?)
+    }
+
+    /// Reads the frame as it arrives, as its length is not known up front.
+    fn read_frame(&mut self, _length: usize) -> Result<Vec<u8>> {
+        read_stream_frame(&mut self.port)
+    }
+}
+
+impl Transport for UartTransport {
+    fn exchange(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+        exchange_frames(self, command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::pi::nfc::mock::MockTransport;
+
+    /// Expects the commands the driver sends on setup.
+    fn setup(transport: MockTransport) -> MockTransport {
+        transport
+            .expect(&[0xd4, 0x02], &[0xd5, 0x03, 0x32, 0x01, 0x06, 0x07])
+            .expect(&[0xd4, 0x14, 0x01, 0x14, 0x01], &[0xd5, 0x15])
+            .expect(&[0xd4, 0x32, 0x05, 0xff, 0x01, 0x01], &[0xd5, 0x33])
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let frame = encode_frame(&[0xd4, 0x02]);
+
+        assert_eq!(
+            frame,
+            [0x00, 0x00, 0xff, 0x02, 0xfe, 0xd4, 0x02, 0x2a, 0x00]
+        );
+        assert_eq!(decode_frame(&frame).unwrap(), Frame::Data(vec![0xd4, 0x02]));
+        assert_eq!(
+            decode_frame(&[0x01, 0x00, 0x00, 0xff, 0x00, 0xff, 0x00]).unwrap(),
+            Frame::Ack
+        );
+    }
+
+    #[test]
+    fn rejects_corrupted_frames() {
+        let mut frame = encode_frame(&[0xd5, 0x03, 0x32]);
+        frame[6] ^= 0x01;
+
+        assert!(decode_frame(&frame).is_err());
+        assert!(decode_frame(&[0x00, 0x00, 0xff, 0x03, 0xfe]).is_err());
+        assert!(decode_frame(&[0x00, 0x00, 0xff, 0x02, 0xfe, 0xd5]).is_err());
+    }
+
+    #[test]
+    fn reads_frames_from_streams() {
+        let mut bytes = vec![0x55, 0x00, 0x00, 0xff, 0x00, 0xff, 0x00];
+        bytes.extend(encode_frame(&[0xd5, 0x15]));
+        let mut stream = &bytes[..];
+
+        assert_eq!(
+            decode_frame(&read_stream_frame(&mut stream).unwrap()).unwrap(),
+            Frame::Ack
+        );
+        assert_eq!(
+            decode_frame(&read_stream_frame(&mut stream).unwrap()).unwrap(),
+            Frame::Data(vec![0xd5, 0x15])
+        );
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn rejects_other_chips() {
+        let transport =
+            MockTransport::new().expect(&[0xd4, 0x02], &[0xd5, 0x03, 0x31, 0x01, 0x06, 0x07]);
+
+        assert!(Pn532Driver::new(transport).is_err());
+    }
+
+    #[test]
+    fn selects_cards() {
+        let transport = setup(MockTransport::new())
+            .expect(
+                &[0xd4, 0x4a, 0x01, 0x00],
+                &[
+                    0xd5, 0x4b, 0x01, 0x01, 0x00, 0x44, 0x00, 0x07, 0x04, 0xa1, 0xb2, 0xc3, 0xd4,
+                    0xe5, 0xf6,
+                ],
+            )
+            .expect(&[0xd4, 0x4a, 0x01, 0x00], &[0xd5, 0x4b, 0x00]);
+        let mut driver = Pn532Driver::new(transport).unwrap();
+
+        assert_eq!(
+            driver.select().unwrap(),
+            Some(vec![0x04, 0xa1, 0xb2, 0xc3, 0xd4, 0xe5, 0xf6])
+        );
+        assert_eq!(driver.select().unwrap(), None);
+    }
+
+    #[test]
+    fn checks_health_and_resets() {
+        let transport = setup(setup(MockTransport::new()))
+            .expect(&[0xd4, 0x02], &[0xd5, 0x03, 0x32, 0x01, 0x06, 0x07])
+            .expect(&[0xd4, 0x02], &[0x00]);
+        let mut driver = Pn532Driver::new(transport).unwrap();
+
+        driver.reset().unwrap();
+        assert!(driver.check_health().is_ok());
+        assert!(driver.check_health().is_err());
+    }
+
+    #[test]
+    fn exchanges_data_with_cards() {
+        let mut read_response = vec![0xd5, 0x41, 0x00];
+        read_response.extend([0xab; 16]);
+
+        let transport = setup(MockTransport::new())
+            .expect(&[0xd4, 0x40, 0x01, 0x30, 0x04], &read_response)
+            .expect(
+                &[0xd4, 0x40, 0x01, 0xa2, 0x04, 0x03, 0x00, 0xfe, 0x00],
+                &[0xd5, 0x41, 0x00],
+            )
+            .expect(&[0xd4, 0x40, 0x01, 0x30, 0x04], &[0xd5, 0x41, 0x01])
+            .expect(&[0xd4, 0x40, 0x01, 0x30, 0x04], &[0xd5, 0x41, 0x02]);
+        let mut driver = Pn532Driver::new(transport).unwrap();
+
+        assert_eq!(
+            driver.transceive(&[0x30, 0x04]).unwrap(),
+            Some(vec![0xab; 16])
+        );
+        assert_eq!(
+            driver
+                .transceive(&[0xa2, 0x04, 0x03, 0x00, 0xfe, 0x00])
+                .unwrap(),
+            Some(vec![ACK])
+        );
+        assert_eq!(driver.transceive(&[0x30, 0x04]).unwrap(), None);
+        assert!(driver.transceive(&[0x30, 0x04]).is_err());
+    }
+}
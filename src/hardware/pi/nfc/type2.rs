@@ -0,0 +1,265 @@
+//! NDEF storage on NFC Forum Type 2 tags, like the NTAG and MIFARE Ultralight
+//! families.
+
+use crate::hardware::pi::nfc::NfcDriver;
+use anyhow::{bail, Context, Result};
+
+const COMMAND_READ: u8 = 0x30;
+const COMMAND_WRITE: u8 = 0xa2;
+const ACK: u8 = 0x0a;
+
+const PAGE_SIZE: usize = 4;
+const CAPABILITY_CONTAINER_PAGE: u8 = 3;
+const DATA_AREA_PAGE: u8 = 4;
+const NDEF_MAGIC: u8 = 0xe1;
+
+/// Largest data area whose pages can all be addressed, ending with the quad
+/// read from page 252.
+const MAX_DATA_AREA_SIZE: usize = (256 - DATA_AREA_PAGE as usize) * PAGE_SIZE;
+
+const TLV_NULL: u8 = 0x00;
+const TLV_NDEF_MESSAGE: u8 = 0x03;
+const TLV_TERMINATOR: u8 = 0xfe;
+
+/// Reads four pages starting at the given one.
+fn read_pages(driver: &mut dyn NfcDriver, page: u8) -> Result<Vec<u8>> {
+    let response = driver
+        .transceive(&[COMMAND_READ, page])?
+        .context("card did not answer")?;
+
+    if response.len() != 4 * PAGE_SIZE {
+        bail!("failed to read page {}", page);
+    }
+
+    Ok(response)
+}
+
+/// Data area of a tag, read on demand.
+struct DataArea<'a> {
+    driver: &'a mut dyn NfcDriver,
+    size: usize,
+    data: Vec<u8>,
+}
+
+impl DataArea<'_> {
+    fn get(&mut self, offset: usize, length: usize) -> Result<&[u8]> {
+        let end = offset + length;
+
+        if end > self.size {
+            bail!("NDEF data exceeds the tag");
+        }
+
+        while self.data.len() < end {
+            let page = DATA_AREA_PAGE as usize + self.data.len() / PAGE_SIZE;
+            let pages = read_pages(self.driver, page as u8)?;
+            self.data.extend_from_slice(&pages);
+        }
+
+        Ok(&self.data[offset..end])
+    }
+}
+
+/// Reads the capability container, returning the size of the data area and
+/// whether it is writable.
+fn read_data_area_size(driver: &mut dyn NfcDriver) -> Result<(usize, bool)> {
+    let capability_container = read_pages(driver, CAPABILITY_CONTAINER_PAGE)?;
+
+    if capability_container[0] != NDEF_MAGIC {
+        bail!("tag is not NDEF formatted");
+    }
+
+    let size = capability_container[2] as usize * 8;
+    let writable = capability_container[3] & 0xf0 == 0;
+
+    if size > MAX_DATA_AREA_SIZE {
+        bail!(
+            "tags with more than {} bytes are not supported",
+            MAX_DATA_AREA_SIZE
+        );
+    }
+
+    Ok((size, writable))
+}
+
+/// Finds the NDEF message in the data area, returning `None` if there is no
+/// NDEF message TLV.
+fn find_message(area: &mut DataArea) -> Result<Option<Vec<u8>>> {
+    let mut offset = 0;
+
+    // Blank tags may be filled with NULL TLVs up to the end.
+    while offset < area.size {
+        let tlv_type = area.get(offset, 1)?[0];
+        offset += 1;
+
+        match tlv_type {
+            TLV_NULL => continue,
+            TLV_TERMINATOR => return Ok(None),
+            _ => {}
+        }
+
+        let mut length = area.get(offset, 1)?[0] as usize;
+        offset += 1;
+
+        if length == 0xff {
+            let bytes = area.get(offset, 2)?;
+            length = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+            offset += 2;
+        }
+
+        if tlv_type == TLV_NDEF_MESSAGE {
+            return Ok(Some(area.get(offset, length)?.to_vec()));
+        }
+
+        offset += length;
+    }
+
+    Ok(None)
+}
+
+/// Reads the raw NDEF message of the selected tag.
+pub fn read_message(driver: &mut dyn NfcDriver) -> Result<Vec<u8>> {
+    let (size, _) = read_data_area_size(driver)?;
+    let mut area = DataArea {
+        driver,
+        size,
+        data: Vec::new(),
+    };
+
+    find_message(&mut area)?.context("tag contains no NDEF message")
+}
+
+/// Writes an NDEF message to the selected tag, which has to be blank, and
+/// verifies it by reading it back.
+pub fn write_message(driver: &mut dyn NfcDriver, message: &[u8]) -> Result<()> {
+    let (size, writable) = read_data_area_size(driver)?;
+
+    if !writable {
+        bail!("tag is write protected");
+    }
+
+    let mut area = DataArea {
+        driver: &mut *driver,
+        size,
+        data: Vec::new(),
+    };
+
+    if find_message(&mut area)?.is_some_and(|message| !message.is_empty()) {
+        bail!("tag is not blank");
+    }
+
+    let mut tlv = vec![TLV_NDEF_MESSAGE];
+
+    if message.len() < 0xff {
+        tlv.push(message.len() as u8);
+    } else {
+        tlv.push(0xff);
+        tlv.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    }
+
+    tlv.extend_from_slice(message);
+    tlv.push(TLV_TERMINATOR);
+
+    if tlv.len() > size {
+        bail!("NDEF message does not fit on the tag");
+    }
+
+    tlv.resize(tlv.len().next_multiple_of(PAGE_SIZE), 0);
+
+    for (index, page_data) in tlv.chunks(PAGE_SIZE).enumerate() {
+        let page = DATA_AREA_PAGE + index as u8;
+        let mut command = vec![COMMAND_WRITE, page];
+        command.extend_from_slice(page_data);
+
+        match driver.transceive(&command)? {
+            Some(response) if response.first().is_some_and(|ack| ack & 0x0f == ACK) => {}
+            _ => bail!("failed to write page {}", page),
+        }
+    }
+
+    if read_message(driver)? != message {
+        bail!("written NDEF message could not be verified");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::pi::nfc::mock::MockTag;
+
+    fn data_area(tlvs: &[u8]) -> Vec<u8> {
+        let mut data = tlvs.to_vec();
+        data.resize(64, 0);
+        data
+    }
+
+    fn find(tag: &mut MockTag) -> Result<Option<Vec<u8>>> {
+        let (size, _) = read_data_area_size(tag)?;
+
+        find_message(&mut DataArea {
+            driver: tag,
+            size,
+            data: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn finds_message_after_other_tlvs() {
+        let mut tag = MockTag::new(&data_area(&[
+            0x00, 0x01, 0x03, 0xa0, 0x0c, 0x34, 0x03, 0x02, 0xd0, 0x00, 0xfe,
+        ]));
+
+        assert_eq!(find(&mut tag).unwrap(), Some(vec![0xd0, 0x00]));
+    }
+
+    #[test]
+    fn finds_message_with_long_length() {
+        let mut tlvs = vec![0x03, 0xff, 0x00, 0x20];
+        tlvs.extend_from_slice(&[0x55; 0x20]);
+        let mut tag = MockTag::new(&data_area(&tlvs));
+
+        assert_eq!(find(&mut tag).unwrap(), Some(vec![0x55; 0x20]));
+    }
+
+    #[test]
+    fn finds_no_message_on_blank_tags() {
+        assert_eq!(find(&mut MockTag::new(&data_area(&[]))).unwrap(), None);
+        assert_eq!(find(&mut MockTag::new(&data_area(&[0xfe]))).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_messages_exceeding_the_tag() {
+        let mut tag = MockTag::new(&data_area(&[0x03, 0x50]));
+
+        assert!(find(&mut tag).is_err());
+    }
+
+    #[test]
+    fn rejects_large_tags() {
+        let mut tag = MockTag::new(&data_area(&[0x03, 0x00, 0xfe]));
+        tag.memory[14] = 0xff;
+
+        assert!(read_message(&mut tag).is_err());
+        assert!(write_message(&mut tag, &[0xd0, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn rejects_unformatted_tags() {
+        let mut tag = MockTag::new(&data_area(&[]));
+        tag.memory[12] = 0x00;
+
+        assert!(read_message(&mut tag).is_err());
+    }
+
+    #[test]
+    fn writes_messages_to_blank_tags() {
+        let mut tag = MockTag::new(&data_area(&[0x03, 0x00, 0xfe]));
+        let message = [0xd1, 0x01, 0x02, 0x54, 0x00, 0x61];
+
+        write_message(&mut tag, &message).unwrap();
+
+        assert_eq!(read_message(&mut tag).unwrap(), message);
+        assert!(write_message(&mut tag, &message).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+use crate::state::PersistedState;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as DecodeError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use tokio::{fs, task};
+use tracing::{info, instrument};
+
+/// RMS level that normalized files are attenuated to.
+const TARGET_LEVEL_DB: f32 = -20.0;
+
+/// Lowest gain applied by normalization, so that broken measurements cannot
+/// mute a file entirely.
+const MIN_GAIN: f32 = 0.1;
+
+/// Remembers per-file gain factors for loudness normalization.
+///
+/// Files are only ever attenuated, never boosted, as boosting would clip on
+/// files which are already mastered close to full scale. Gains are measured
+/// with [`measure_gain`] when files are added to the cache, so playback only
+/// looks them up.
+#[derive(Debug)]
+pub struct LoudnessNormalizer {
+    state: PersistedState<LoudnessState>,
+}
+
+impl LoudnessNormalizer {
+    /// Loads the gains measured before, dropping those of files which are
+    /// gone in the meantime.
+    pub async fn new(data_path: &Path) -> Result<Self> {
+        let mut normalizer = Self {
+            state: PersistedState::new(data_path, "loudness", None).await?,
+        };
+        let mut missing = Vec::new();
+
+        for key in normalizer.state.gains.keys() {
+            if !fs::try_exists(key).await.unwrap_or(true) {
+                missing.push(PathBuf::from(key));
+            }
+        }
+
+        normalizer.forget(&missing)?;
+        Ok(normalizer)
+    }
+
+    /// Returns the gain measured for the given file, if any.
+    pub fn gain(&self, path: &Path) -> Option<f32> {
+        self.state.gains.get(&Self::key(path)).copied()
+    }
+
+    pub fn insert(&mut self, path: &Path, gain: f32) -> Result<()> {
+        self.state.mutate(|state| {
+            state.gains.insert(Self::key(path), gain);
+        })
+    }
+
+    /// Drops the gains of files removed from the cache.
+    pub fn forget(&mut self, paths: &[PathBuf]) -> Result<()> {
+        if !paths.iter().any(|path| self.gain(path).is_some()) {
+            return Ok(());
+        }
+
+        self.state.mutate(|state| {
+            for path in paths {
+                state.gains.remove(&Self::key(path));
+            }
+        })
+    }
+
+    fn key(path: &Path) -> String {
+        path.to_string_lossy().into_owned()
+    }
+}
+
+/// Decodes the file off the runtime thread and returns the gain which
+/// normalizes its loudness.
+#[instrument]
+pub async fn measure_gain(path: &Path) -> Result<f32> {
+    let level = task::spawn_blocking({
+        let path = path.to_path_buf();
+        move || measure_rms_db(&path)
+    })
+    .await??;
+    let gain = gain_for_level(level);
+
+    info!("measured {:.1} dBFS, normalization gain {:.2}", level, gain);
+    Ok(gain)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct LoudnessState {
+    gains: HashMap<String, f32>,
+}
+
+fn gain_for_level(level: f32) -> f32 {
+    if !level.is_finite() {
+        return 1.0;
+    }
+
+    10f32
+        .powf((TARGET_LEVEL_DB - level) / 20.0)
+        .clamp(MIN_GAIN, 1.0)
+}
+
+/// Decodes an MP3 file and returns its RMS level in dBFS.
+fn measure_rms_db(path: &Path) -> Result<f32> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("mp3");
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+    let track = format.default_track().context("file contains no track")?;
+    let track_id = track.id;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut sum = 0.0f64;
+    let mut count = 0u64;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        buffer.copy_interleaved_ref(decoded);
+
+        for sample in buffer.samples() {
+            sum += f64::from(*sample).powi(2);
+        }
+
+        count += buffer.samples().len() as u64;
+    }
+
+    if count == 0 {
+        return Ok(f32::NEG_INFINITY);
+    }
+
+    Ok((10.0 * (sum / count as f64).log10()) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attenuates_loud_files_only() {
+        assert_eq!(gain_for_level(TARGET_LEVEL_DB), 1.0);
+        assert_eq!(gain_for_level(-30.0), 1.0);
+        assert!((gain_for_level(-14.0) - 0.5).abs() < 0.01);
+        assert_eq!(gain_for_level(0.0), MIN_GAIN);
+    }
+
+    #[test]
+    fn leaves_unmeasurable_files_alone() {
+        assert_eq!(gain_for_level(f32::NEG_INFINITY), 1.0);
+        assert_eq!(gain_for_level(f32::NAN), 1.0);
+    }
+
+    #[tokio::test]
+    async fn remembers_and_forgets_gains() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let path = data_dir.path().join("achievement.mp3");
+        let mut normalizer = LoudnessNormalizer::new(data_dir.path()).await.unwrap();
+
+        normalizer.insert(&path, 0.5).unwrap();
+        assert_eq!(normalizer.gain(&path), Some(0.5));
+
+        normalizer.forget(std::slice::from_ref(&path)).unwrap();
+        assert_eq!(normalizer.gain(&path), None);
+    }
+
+    #[tokio::test]
+    async fn drops_gains_of_missing_files_on_load() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let kept = data_dir.path().join("kept.mp3");
+        let removed = data_dir.path().join("removed.mp3");
+        std::fs::write(&kept, b"audio").unwrap();
+        std::fs::write(
+            data_dir.path().join("loudness.state"),
+            toml::to_string(&LoudnessState {
+                gains: HashMap::from([
+                    (LoudnessNormalizer::key(&kept), 0.5),
+                    (LoudnessNormalizer::key(&removed), 0.25),
+                ]),
+            })
+            .unwrap(),
+        )
+        .unwrap();
+
+        let normalizer = LoudnessNormalizer::new(data_dir.path()).await.unwrap();
+
+        assert_eq!(normalizer.gain(&kept), Some(0.5));
+        assert_eq!(normalizer.gain(&removed), None);
+    }
+
+    #[tokio::test]
+    async fn fails_to_measure_missing_files() {
+        let data_dir = tempfile::tempdir().unwrap();
+
+        assert!(measure_gain(&data_dir.path().join("missing.mp3"))
+            .await
+            .is_err());
+    }
+}
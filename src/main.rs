@@ -1,6 +1,10 @@
 use crate::audio::{AudioPlayer, VolumeControlTask};
+use crate::config::{load_config, Config};
 use crate::engine::{Engine, EngineProps};
-use crate::hardware::{init_hardware, HardwareContext, InitSubsystems, Peripherals};
+use crate::gestures::GestureTask;
+use crate::hardware::{data_path, init_hardware, HardwareContext, InitSubsystems, Peripherals};
+use crate::network::watch_status;
+use crate::playback::PlaybackTask;
 #[cfg(feature = "hardware-emulation")]
 use crate::thread::supervised_thread;
 use crate::thread::unwrap_threads;
@@ -8,8 +12,9 @@ use anyhow::{bail, Result};
 use bloop_client_framework::{BloopClient, RootCertSource};
 use std::env;
 use std::future::Future;
+use std::sync::Arc;
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc;
+use tokio::sync::{broadcast, mpsc};
 use tokio::time::Duration;
 use tokio_graceful_shutdown::{
     FutureExt, IntoSubsystem, SubsystemBuilder, SubsystemHandle, Toplevel,
@@ -17,26 +22,49 @@ use tokio_graceful_shutdown::{
 use tokio_util::sync::CancellationToken;
 use tracing_subscriber::EnvFilter;
 
+mod access;
 mod audio;
+mod cache;
+mod config;
 mod engine;
+mod gestures;
 mod hardware;
+mod loudness;
+mod ndef;
+mod network;
+mod palette;
+mod playback;
+mod provisioning;
+#[cfg(test)]
+mod recording;
+mod rescan;
 mod state;
+mod status;
 mod thread;
+mod watchdog;
 
 fn main() -> Result<()> {
     let env_filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new("error,bloop_box=info"));
     tracing_subscriber::fmt().with_env_filter(env_filter).init();
 
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.first().is_some_and(|command| command == "provision") {
+        return provisioning::run_cli(&args[1..]);
+    }
+
+    let config = load_config::<Config>()?;
     let shutdown_token = CancellationToken::new();
     let hardware = init_hardware(shutdown_token.clone())?;
 
-    run(hardware, shutdown_token)?;
+    run(config, hardware, shutdown_token)?;
 
     Ok(())
 }
 
 fn run_async_runtime(
+    config: Config,
     peripherals: Peripherals,
     init_subsystems: InitSubsystems,
     shutdown_token: CancellationToken,
@@ -53,27 +81,52 @@ fn run_async_runtime(
 
         let start_subsystems = init_subsystems()?;
 
-        let audio_player = AudioPlayer::new().await?;
-        let (volume_range_tx, volume_range_rx) = mpsc::channel(16);
+        let data_path = data_path().await?;
+        let audio_player = AudioPlayer::new(&config.audio, &data_path).await?;
+        let (volume_tx, volume_rx) = mpsc::channel(16);
+        let (button_action_tx, button_action_rx) = broadcast::channel(16);
         let volume_control_task = VolumeControlTask::new(
-            volume_range_rx,
-            peripherals.button_receiver,
+            volume_rx,
+            button_action_tx.subscribe(),
             audio_player.clone(),
+            &data_path,
         )
         .await?;
+        let (playback_tx, playback_rx) = mpsc::channel(playback::QUEUE_SIZE);
+        let playback_task = PlaybackTask::new(
+            playback_rx,
+            audio_player.clone(),
+            peripherals.led_controller.clone(),
+            &config.palette,
+        );
+        let gesture_task = GestureTask::new(
+            peripherals.button_receiver,
+            peripherals.action_receiver,
+            button_action_tx,
+            config.gestures,
+        );
 
         let network_client = BloopClient::builder()
             .root_cert_source(root_cert_source)
             .build()?;
-        let network_status = network_client.status();
+        let network_status = watch_status(&network_client);
 
         let engine = Engine::new(EngineProps {
+            config: config.engine,
+            data_path,
             led_controller: peripherals.led_controller,
-            nfc_reader: peripherals.nfc_reader,
-            network_client: network_client.clone(),
+            nfc_readers: peripherals.nfc_readers,
+            nfc_health_rx: peripherals.nfc_health_rx,
+            network_client: Arc::new(network_client.clone()),
             audio_player,
+            playback_tx,
             network_status,
-            volume_range_tx,
+            volume_tx,
+            audio_cache_config: config.audio_cache,
+            palette: config.palette,
+            button_action_rx,
+            access_config: config.access,
+            rescan_config: config.rescan,
         })
         .await?;
 
@@ -96,11 +149,19 @@ fn run_async_runtime(
                 },
             ));
 
+            s.start(SubsystemBuilder::new(
+                "Gestures",
+                gesture_task.into_subsystem(),
+            ));
             s.start(SubsystemBuilder::new(
                 "VolumeControl",
                 volume_control_task.into_subsystem(),
             ));
             s.start(SubsystemBuilder::new("Engine", engine.into_subsystem()));
+            s.start(SubsystemBuilder::new(
+                "Playback",
+                playback_task.into_subsystem(),
+            ));
             s.start(SubsystemBuilder::new(
                 "BloopClient",
                 network_client.into_subsystem(),
@@ -116,8 +177,13 @@ fn run_async_runtime(
 }
 
 #[cfg(not(feature = "hardware-emulation"))]
-fn run(hardware_context: HardwareContext, shutdown_token: CancellationToken) -> Result<()> {
+fn run(
+    config: Config,
+    hardware_context: HardwareContext,
+    shutdown_token: CancellationToken,
+) -> Result<()> {
     let result = run_async_runtime(
+        config,
         hardware_context.peripherals,
         hardware_context.init_subsystems,
         shutdown_token.clone(),
@@ -135,7 +201,11 @@ fn run(hardware_context: HardwareContext, shutdown_token: CancellationToken) ->
 }
 
 #[cfg(feature = "hardware-emulation")]
-fn run(hardware_context: HardwareContext, shutdown_token: CancellationToken) -> Result<()> {
+fn run(
+    config: Config,
+    hardware_context: HardwareContext,
+    shutdown_token: CancellationToken,
+) -> Result<()> {
     let HardwareContext {
         peripherals,
         mut threads,
@@ -145,7 +215,7 @@ fn run(hardware_context: HardwareContext, shutdown_token: CancellationToken) ->
 
     threads.push(supervised_thread("runtime", shutdown_token.clone(), {
         let shutdown_token = shutdown_token.clone();
-        move || run_async_runtime(peripherals, init_subsystems, shutdown_token)
+        move || run_async_runtime(config, peripherals, init_subsystems, shutdown_token)
     })?);
 
     let result = run_ui();
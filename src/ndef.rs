@@ -0,0 +1,500 @@
+//! NDEF messages as stored on config cards.
+//!
+//! A config payload, like `c["host",443,"id","secret"]`, can be stored in a
+//! text record, an `application/vnd.bloop-box+json` MIME record or a
+//! `bloopbox:` URI record. The first record in one of these forms is used.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// MIME type of records carrying a config payload.
+pub const CONFIG_MIME_TYPE: &str = "application/vnd.bloop-box+json";
+
+/// Scheme of URI records carrying a config payload.
+pub const CONFIG_URI_SCHEME: &str = "bloopbox:";
+
+const FLAG_MESSAGE_BEGIN: u8 = 0x80;
+const FLAG_MESSAGE_END: u8 = 0x40;
+const FLAG_CHUNK: u8 = 0x20;
+const FLAG_SHORT_RECORD: u8 = 0x10;
+const FLAG_ID_LENGTH: u8 = 0x08;
+const TNF_MASK: u8 = 0x07;
+
+/// Abbreviations of URI records, indexed by their identifier code.
+const URI_PREFIXES: [&str; 36] = [
+    "",
+    "http://www.",
+    "https://www.",
+    "http://",
+    "https://",
+    "tel:",
+    "mailto:",
+    "ftp://anonymous:anonymous@",
+    "ftp://ftp.",
+    "ftps://",
+    "sftp://",
+    "smb://",
+    "nfs://",
+    "ftp://",
+    "dav://",
+    "news:",
+    "telnet://",
+    "imap:",
+    "rtsp://",
+    "urn:",
+    "pop:",
+    "sip:",
+    "sips:",
+    "tftp:",
+    "btspp://",
+    "btl2cap://",
+    "btgoep://",
+    "tcpobex://",
+    "irdaobex://",
+    "file://",
+    "urn:epc:id:",
+    "urn:epc:tag:",
+    "urn:epc:pat:",
+    "urn:epc:raw:",
+    "urn:epc:",
+    "urn:nfc:",
+];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("NDEF message is truncated")]
+    Truncated,
+    #[error("chunked NDEF records are not supported")]
+    Chunked,
+    #[error("NDEF message is empty")]
+    Empty,
+}
+
+/// Type name format of a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tnf {
+    Empty,
+    WellKnown,
+    Mime,
+    AbsoluteUri,
+    External,
+    Unknown,
+    Unchanged,
+    Reserved,
+}
+
+impl Tnf {
+    fn from_bits(bits: u8) -> Self {
+        match bits & TNF_MASK {
+            0 => Self::Empty,
+            1 => Self::WellKnown,
+            2 => Self::Mime,
+            3 => Self::AbsoluteUri,
+            4 => Self::External,
+            5 => Self::Unknown,
+            6 => Self::Unchanged,
+            _ => Self::Reserved,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            Self::Empty => 0,
+            Self::WellKnown => 1,
+            Self::Mime => 2,
+            Self::AbsoluteUri => 3,
+            Self::External => 4,
+            Self::Unknown => 5,
+            Self::Unchanged => 6,
+            Self::Reserved => 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub tnf: Tnf,
+    pub record_type: Vec<u8>,
+    pub payload: Vec<u8>,
+}
+
+impl Record {
+    /// Creates a UTF-8 text record.
+    pub fn text(language: &str, text: &str) -> Self {
+        let mut payload = vec![language.len() as u8 & 0x3f];
+        payload.extend_from_slice(language.as_bytes());
+        payload.extend_from_slice(text.as_bytes());
+
+        Self {
+            tnf: Tnf::WellKnown,
+            record_type: b"T".to_vec(),
+            payload,
+        }
+    }
+
+    pub fn mime(mime_type: &str, payload: &[u8]) -> Self {
+        Self {
+            tnf: Tnf::Mime,
+            record_type: mime_type.as_bytes().to_vec(),
+            payload: payload.to_vec(),
+        }
+    }
+
+    /// Creates a URI record without abbreviation.
+    pub fn uri(uri: &str) -> Self {
+        let mut payload = vec![0];
+        payload.extend_from_slice(uri.as_bytes());
+
+        Self {
+            tnf: Tnf::WellKnown,
+            record_type: b"U".to_vec(),
+            payload,
+        }
+    }
+
+    /// Returns the text of a text record.
+    pub fn as_text(&self) -> Option<String> {
+        if self.tnf != Tnf::WellKnown || self.record_type != b"T" {
+            return None;
+        }
+
+        let (&status, rest) = self.payload.split_first()?;
+        let text = rest.get((status & 0x3f) as usize..)?;
+
+        if status & 0x80 == 0 {
+            return String::from_utf8(text.to_vec()).ok();
+        }
+
+        decode_utf16(text)
+    }
+
+    /// Returns the full URI of a URI record.
+    pub fn as_uri(&self) -> Option<String> {
+        match self.tnf {
+            Tnf::WellKnown if self.record_type == b"U" => {
+                let (&code, rest) = self.payload.split_first()?;
+                let prefix = URI_PREFIXES.get(code as usize)?;
+                let rest = std::str::from_utf8(rest).ok()?;
+
+                Some(format!("{prefix}{rest}"))
+            }
+            Tnf::AbsoluteUri => String::from_utf8(self.record_type.clone()).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the config payload carried by the record, if it has one of the
+    /// supported forms.
+    pub fn config_payload(&self) -> Option<String> {
+        if let Some(text) = self.as_text() {
+            return Some(text);
+        }
+
+        if self.tnf == Tnf::Mime
+            && self
+                .record_type
+                .eq_ignore_ascii_case(CONFIG_MIME_TYPE.as_bytes())
+        {
+            return String::from_utf8(self.payload.clone()).ok();
+        }
+
+        let uri = self.as_uri()?;
+
+        if !uri
+            .get(..CONFIG_URI_SCHEME.len())?
+            .eq_ignore_ascii_case(CONFIG_URI_SCHEME)
+        {
+            return None;
+        }
+
+        percent_decode(&uri[CONFIG_URI_SCHEME.len()..])
+    }
+}
+
+/// Kind of record a config payload is stored in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RecordKind {
+    #[default]
+    Text,
+    Mime,
+    Uri,
+}
+
+/// A config payload along with the kind of record to store it in.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigRecord {
+    pub payload: String,
+    #[serde(default)]
+    pub kind: RecordKind,
+}
+
+impl ConfigRecord {
+    pub fn to_record(&self) -> Record {
+        match self.kind {
+            RecordKind::Text => Record::text("en", &self.payload),
+            RecordKind::Mime => Record::mime(CONFIG_MIME_TYPE, self.payload.as_bytes()),
+            RecordKind::Uri => Record::uri(&format!("{}{}", CONFIG_URI_SCHEME, self.payload)),
+        }
+    }
+}
+
+/// Returns the config payload of the first record which carries one.
+pub fn config_payload(records: &[Record]) -> Option<String> {
+    records.iter().find_map(Record::config_payload)
+}
+
+pub fn parse_message(data: &[u8]) -> Result<Vec<Record>, Error> {
+    let mut records = Vec::new();
+    let mut rest = data;
+
+    loop {
+        let (&header, tail) = rest.split_first().ok_or(Error::Truncated)?;
+        rest = tail;
+
+        if header & FLAG_CHUNK != 0 {
+            return Err(Error::Chunked);
+        }
+
+        let type_length = take(&mut rest, 1)?[0] as usize;
+        let payload_length = if header & FLAG_SHORT_RECORD != 0 {
+            take(&mut rest, 1)?[0] as usize
+        } else {
+            let bytes = take(&mut rest, 4)?;
+            u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize
+        };
+        let id_length = if header & FLAG_ID_LENGTH != 0 {
+            take(&mut rest, 1)?[0] as usize
+        } else {
+            0
+        };
+
+        let record_type = take(&mut rest, type_length)?.to_vec();
+        take(&mut rest, id_length)?;
+        let payload = take(&mut rest, payload_length)?.to_vec();
+
+        records.push(Record {
+            tnf: Tnf::from_bits(header),
+            record_type,
+            payload,
+        });
+
+        if header & FLAG_MESSAGE_END != 0 || rest.is_empty() {
+            break;
+        }
+    }
+
+    if records.iter().all(|record| record.tnf == Tnf::Empty) {
+        return Err(Error::Empty);
+    }
+
+    Ok(records)
+}
+
+pub fn encode_message(records: &[Record]) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for (index, record) in records.iter().enumerate() {
+        let mut header = record.tnf.bits();
+
+        if index == 0 {
+            header |= FLAG_MESSAGE_BEGIN;
+        }
+
+        if index == records.len() - 1 {
+            header |= FLAG_MESSAGE_END;
+        }
+
+        let short = record.payload.len() <= u8::MAX as usize;
+
+        if short {
+            header |= FLAG_SHORT_RECORD;
+        }
+
+        data.push(header);
+        data.push(record.record_type.len() as u8);
+
+        if short {
+            data.push(record.payload.len() as u8);
+        } else {
+            data.extend_from_slice(&(record.payload.len() as u32).to_be_bytes());
+        }
+
+        data.extend_from_slice(&record.record_type);
+        data.extend_from_slice(&record.payload);
+    }
+
+    data
+}
+
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Result<&'a [u8], Error> {
+    if data.len() < length {
+        return Err(Error::Truncated);
+    }
+
+    let (head, tail) = data.split_at(length);
+    *data = tail;
+
+    Ok(head)
+}
+
+/// Decodes UTF-16 text, big endian unless a byte order mark says otherwise.
+fn decode_utf16(data: &[u8]) -> Option<String> {
+    let (little_endian, data) = match data {
+        [0xff, 0xfe, rest @ ..] => (true, rest),
+        [0xfe, 0xff, rest @ ..] => (false, rest),
+        _ => (false, data),
+    };
+
+    let units = data
+        .chunks_exact(2)
+        .map(|pair| {
+            if little_endian {
+                u16::from_le_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_be_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect::<Vec<_>>();
+
+    String::from_utf16(&units).ok()
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_text_records() {
+        let records = parse_message(&[
+            0xd1, 0x01, 0x08, 0x54, 0x02, 0x65, 0x6e, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+        ])
+        .unwrap();
+
+        assert_eq!(records, [Record::text("en", "hello")]);
+        assert_eq!(records[0].as_text().as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn parses_long_records_with_ids() {
+        let mut data = vec![0xca, 0x03, 0x00, 0x00, 0x01, 0x00, 0x01];
+        data.extend_from_slice(b"a/b");
+        data.push(0x2a);
+        data.extend_from_slice(&[0x78; 256]);
+
+        let records = parse_message(&data).unwrap();
+
+        assert_eq!(records, [Record::mime("a/b", &[0x78; 256])]);
+    }
+
+    #[test]
+    fn decodes_utf16_text() {
+        let record = Record {
+            tnf: Tnf::WellKnown,
+            record_type: b"T".to_vec(),
+            payload: vec![0x82, 0x65, 0x6e, 0xff, 0xfe, 0x63, 0x00, 0x5b, 0x00],
+        };
+
+        assert_eq!(record.as_text().as_deref(), Some("c["));
+    }
+
+    #[test]
+    fn reads_only_text_records_as_text() {
+        let payload = b"\x02enc[\"host\"]".to_vec();
+        let records = [
+            Record {
+                tnf: Tnf::WellKnown,
+                record_type: b"Sp".to_vec(),
+                payload: payload.clone(),
+            },
+            Record {
+                tnf: Tnf::External,
+                record_type: b"T".to_vec(),
+                payload,
+            },
+        ];
+
+        assert!(records.iter().all(|record| record.as_text().is_none()));
+        assert_eq!(config_payload(&records), None);
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        assert!(matches!(parse_message(&[]), Err(Error::Truncated)));
+        assert!(matches!(
+            parse_message(&[0xd1, 0x01, 0x08, 0x54, 0x02]),
+            Err(Error::Truncated)
+        ));
+        assert!(matches!(
+            parse_message(&[0xb1, 0x01, 0x00, 0x54]),
+            Err(Error::Chunked)
+        ));
+        assert!(matches!(
+            parse_message(&[0xd0, 0x00, 0x00]),
+            Err(Error::Empty)
+        ));
+    }
+
+    #[test]
+    fn round_trips_config_records() {
+        for kind in [RecordKind::Text, RecordKind::Mime, RecordKind::Uri] {
+            let record = ConfigRecord {
+                payload: r#"c["host",443,"id","secret"]"#.to_string(),
+                kind,
+            };
+            let records = parse_message(&encode_message(&[record.to_record()])).unwrap();
+
+            assert_eq!(config_payload(&records), Some(record.payload));
+        }
+    }
+
+    #[test]
+    fn finds_config_payload_in_uri_records() {
+        let records = [
+            Record::uri("https://example.com"),
+            Record::uri("BloopBox:c%5B%22host%22%5D"),
+        ];
+
+        assert_eq!(config_payload(&records).as_deref(), Some(r#"c["host"]"#));
+    }
+
+    #[test]
+    fn expands_abbreviated_uris() {
+        let record = Record {
+            tnf: Tnf::WellKnown,
+            record_type: b"U".to_vec(),
+            payload: b"\x04example.com".to_vec(),
+        };
+
+        assert_eq!(record.as_uri().as_deref(), Some("https://example.com"));
+        assert_eq!(record.config_payload(), None);
+    }
+
+    #[test]
+    fn ignores_other_records() {
+        let records = [
+            Record::mime("application/json", b"{}"),
+            Record::uri("bloopbox:%zz"),
+        ];
+
+        assert_eq!(config_payload(&records), None);
+    }
+}
@@ -0,0 +1,121 @@
+use async_trait::async_trait;
+use bloop_client_framework::{
+    AudioProvider, BloopClient, ConnectionConfig, ConnectionStatus, PreloadOutcome, RequestError,
+};
+use bloop_protocol::message::AchievementRecord;
+use bloop_protocol::{Capabilities, DataHash, NfcUid};
+use std::future::Future;
+use tokio::sync::watch;
+use uuid::Uuid;
+
+/// Connection state of the [`NetworkClient`].
+///
+/// Mirrors [`ConnectionStatus`], which cannot be constructed outside of the
+/// framework.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkStatus {
+    Unconfigured,
+    Disconnected,
+    Connected {
+        /// Whether the server supports the preload check.
+        preload_check: bool,
+    },
+    InvalidCredentials,
+    Shutdown,
+}
+
+impl From<ConnectionStatus> for NetworkStatus {
+    fn from(status: ConnectionStatus) -> Self {
+        match status {
+            ConnectionStatus::Unconfigured => Self::Unconfigured,
+            ConnectionStatus::Connected { capabilities, .. } => Self::Connected {
+                preload_check: capabilities.contains(Capabilities::PreloadCheck),
+            },
+            ConnectionStatus::InvalidCredentials => Self::InvalidCredentials,
+            ConnectionStatus::Shutdown => Self::Shutdown,
+            _ => Self::Disconnected,
+        }
+    }
+}
+
+/// Follows the connection status of the client until either side goes away.
+pub fn watch_status(client: &BloopClient) -> watch::Receiver<NetworkStatus> {
+    let mut status_rx = client.status();
+    let (status_tx, network_status) = watch::channel((*status_rx.borrow_and_update()).into());
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                result = status_rx.changed() => {
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                _ = status_tx.closed() => break,
+            }
+
+            status_tx.send_replace((*status_rx.borrow_and_update()).into());
+        }
+    });
+
+    network_status
+}
+
+/// Requests of the engine to the Bloop server, sent through a [`BloopClient`]
+/// outside of tests.
+#[async_trait]
+pub trait NetworkClient: Send + Sync {
+    async fn configure(&self, config: Option<ConnectionConfig>) -> Result<(), RequestError>;
+
+    async fn bloop(&self, nfc_uid: NfcUid) -> Result<Vec<AchievementRecord>, RequestError>;
+
+    async fn retrieve_audio(&self, achievement_id: Uuid) -> Result<Vec<u8>, RequestError>;
+
+    async fn preload_check(
+        &self,
+        audio_manifest_hash: Option<DataHash>,
+    ) -> Result<PreloadOutcome, RequestError>;
+
+    async fn shutdown(&self);
+}
+
+#[async_trait]
+impl NetworkClient for BloopClient {
+    async fn configure(&self, config: Option<ConnectionConfig>) -> Result<(), RequestError> {
+        BloopClient::configure(self, config).await
+    }
+
+    async fn bloop(&self, nfc_uid: NfcUid) -> Result<Vec<AchievementRecord>, RequestError> {
+        BloopClient::bloop(self, nfc_uid).await
+    }
+
+    async fn retrieve_audio(&self, achievement_id: Uuid) -> Result<Vec<u8>, RequestError> {
+        BloopClient::retrieve_audio(self, achievement_id).await
+    }
+
+    async fn preload_check(
+        &self,
+        audio_manifest_hash: Option<DataHash>,
+    ) -> Result<PreloadOutcome, RequestError> {
+        BloopClient::preload_check(self, audio_manifest_hash).await
+    }
+
+    async fn shutdown(&self) {
+        self.clone().shutdown().await
+    }
+}
+
+/// Downloads achievement audio into the [`AudioCache`] through a
+/// [`NetworkClient`].
+///
+/// [`AudioCache`]: bloop_client_framework::AudioCache
+pub struct AudioDownloader<'a>(pub &'a dyn NetworkClient);
+
+impl AudioProvider for AudioDownloader<'_> {
+    fn retrieve_audio(
+        &mut self,
+        achievement_id: Uuid,
+    ) -> impl Future<Output = Result<Vec<u8>, RequestError>> + Send {
+        self.0.retrieve_audio(achievement_id)
+    }
+}
@@ -0,0 +1,284 @@
+//! Mapping of engine states to LED colors and animations.
+
+use crate::hardware::led::{Animation, Color};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Semantic states shown on the status LED.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatusLed {
+    IdleConnected,
+    Busy,
+    ConfigOk,
+    ConfigError,
+    Unconfigured,
+    InvalidCredentials,
+    Offline,
+    Preloading,
+    AwaitingConfigCard,
+    /// Waiting for a blank card to write a config card to.
+    AwaitingBlankCard,
+    AudioUnavailable,
+    BloopAccepted,
+    BloopThrottled,
+    BloopRejected,
+    BloopFailed,
+    /// The card can not be used for blooping, e.g. as it presents a random
+    /// UID.
+    UnsupportedCard,
+    /// An NFC reader is unresponsive and being reset.
+    ReaderFault,
+    /// Shown once for every achievement awarded by a bloop.
+    Achievement,
+    /// Shown briefly whenever the volume is changed through the buttons.
+    VolumeChange,
+}
+
+/// How a state is shown, written as e.g.
+/// `{ pattern = "breathing", color = "#00ff00" }` in the config.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "pattern", rename_all = "kebab-case")]
+pub enum LedStyle {
+    Static {
+        color: Color,
+    },
+    Breathing {
+        color: Color,
+    },
+    /// Blinks continuously.
+    Blinking {
+        color: Color,
+    },
+    /// Blinks the given number of times followed by a pause.
+    BlinkCode {
+        color: Color,
+        count: u32,
+    },
+    /// Alternates between two colors.
+    Alternate {
+        color: Color,
+        second: Color,
+        #[serde(default = "LedStyle::default_period_ms")]
+        period_ms: u64,
+    },
+    /// Blinks once, then returns to the previous pattern.
+    Pulse {
+        color: Color,
+    },
+    /// Shows the color for the given time, then returns to the previous
+    /// pattern.
+    Flash {
+        color: Color,
+        #[serde(default = "LedStyle::default_flash_ms")]
+        duration_ms: u64,
+    },
+    /// Fades in from another color, black by default, and stays.
+    Fade {
+        color: Color,
+        #[serde(default = "LedStyle::default_fade_from")]
+        from: Color,
+        #[serde(default = "LedStyle::default_period_ms")]
+        duration_ms: u64,
+    },
+}
+
+impl LedStyle {
+    fn default_period_ms() -> u64 {
+        500
+    }
+
+    fn default_flash_ms() -> u64 {
+        150
+    }
+
+    fn default_fade_from() -> Color {
+        Color::new(0, 0, 0)
+    }
+
+    fn animation(&self) -> Animation {
+        match *self {
+            Self::Static { color } => Animation::solid(color),
+            Self::Breathing { color } => Animation::breathing(color),
+            Self::Blinking { color } => {
+                Animation::alternate(color, Color::new(0, 0, 0), Duration::from_millis(250))
+            }
+            Self::BlinkCode { color, count } => Animation::blink_code(color, count),
+            Self::Alternate {
+                color,
+                second,
+                period_ms,
+            } => Animation::alternate(color, second, Duration::from_millis(period_ms)),
+            Self::Pulse { color } => Animation::blink(color, 1),
+            Self::Flash { color, duration_ms } => {
+                Animation::flash(color, Duration::from_millis(duration_ms))
+            }
+            Self::Fade {
+                color,
+                from,
+                duration_ms,
+            } => Animation::fade(from, color, Duration::from_millis(duration_ms)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PalettePreset {
+    #[default]
+    Default,
+    /// Okabe-Ito colors, which avoid red/green distinctions and differ in
+    /// pattern where colors are similar.
+    ColorBlind,
+}
+
+impl PalettePreset {
+    fn style(&self, state: StatusLed) -> LedStyle {
+        use LedStyle::*;
+        use StatusLed::*;
+
+        match self {
+            Self::Default => match state {
+                IdleConnected => Static {
+                    color: Color::GREEN,
+                },
+                Busy => Static {
+                    color: Color::MAGENTA,
+                },
+                ConfigOk => Static { color: Color::CYAN },
+                ConfigError => Static { color: Color::RED },
+                Unconfigured => Breathing {
+                    color: Color::YELLOW,
+                },
+                InvalidCredentials => Breathing { color: Color::RED },
+                Offline => Breathing { color: Color::BLUE },
+                Preloading => Breathing { color: Color::CYAN },
+                AwaitingConfigCard => Breathing {
+                    color: Color::MAGENTA,
+                },
+                AwaitingBlankCard => Alternate {
+                    color: Color::MAGENTA,
+                    second: Color::CYAN,
+                    period_ms: 500,
+                },
+                AudioUnavailable => BlinkCode {
+                    color: Color::RED,
+                    count: 2,
+                },
+                BloopAccepted => Blinking {
+                    color: Color::GREEN,
+                },
+                BloopThrottled => Static {
+                    color: Color::YELLOW,
+                },
+                BloopRejected => Static { color: Color::RED },
+                BloopFailed => Blinking { color: Color::RED },
+                UnsupportedCard => Blinking {
+                    color: Color::YELLOW,
+                },
+                ReaderFault => BlinkCode {
+                    color: Color::RED,
+                    count: 3,
+                },
+                Achievement => Pulse {
+                    color: Color::new(255, 255, 255),
+                },
+                VolumeChange => Flash {
+                    color: Color::new(255, 255, 255),
+                    duration_ms: 150,
+                },
+            },
+            Self::ColorBlind => {
+                const ORANGE: Color = Color::new(230, 159, 0);
+                const SKY_BLUE: Color = Color::new(86, 180, 233);
+                const BLUISH_GREEN: Color = Color::new(0, 158, 115);
+                const YELLOW: Color = Color::new(240, 228, 66);
+                const BLUE: Color = Color::new(0, 114, 178);
+                const VERMILLION: Color = Color::new(213, 94, 0);
+                const REDDISH_PURPLE: Color = Color::new(204, 121, 167);
+
+                match state {
+                    IdleConnected => Static { color: BLUE },
+                    Busy => Static { color: YELLOW },
+                    ConfigOk => Static {
+                        color: BLUISH_GREEN,
+                    },
+                    ConfigError => Blinking { color: VERMILLION },
+                    Unconfigured => Breathing { color: ORANGE },
+                    InvalidCredentials => BlinkCode {
+                        color: VERMILLION,
+                        count: 3,
+                    },
+                    Offline => Breathing { color: SKY_BLUE },
+                    Preloading => Alternate {
+                        color: SKY_BLUE,
+                        second: BLUE,
+                        period_ms: 500,
+                    },
+                    AwaitingConfigCard => Breathing {
+                        color: REDDISH_PURPLE,
+                    },
+                    AwaitingBlankCard => Alternate {
+                        color: REDDISH_PURPLE,
+                        second: BLUISH_GREEN,
+                        period_ms: 500,
+                    },
+                    AudioUnavailable => BlinkCode {
+                        color: VERMILLION,
+                        count: 2,
+                    },
+                    BloopAccepted => Blinking {
+                        color: BLUISH_GREEN,
+                    },
+                    BloopThrottled => Static { color: ORANGE },
+                    BloopRejected => Static { color: VERMILLION },
+                    BloopFailed => Blinking { color: VERMILLION },
+                    UnsupportedCard => Blinking { color: ORANGE },
+                    ReaderFault => BlinkCode {
+                        color: VERMILLION,
+                        count: 4,
+                    },
+                    Achievement => Pulse {
+                        color: Color::new(255, 255, 255),
+                    },
+                    VolumeChange => Flash {
+                        color: Color::new(255, 255, 255),
+                        duration_ms: 150,
+                    },
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    preset: PalettePreset,
+    /// Overrides for individual states.
+    states: HashMap<StatusLed, LedStyle>,
+    /// Whether to show [`StatusLed::Achievement`] for every awarded
+    /// achievement.
+    pub achievement_pulse: bool,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            preset: PalettePreset::default(),
+            states: HashMap::new(),
+            achievement_pulse: true,
+        }
+    }
+}
+
+impl Palette {
+    pub fn animation(&self, state: StatusLed) -> Animation {
+        self.states
+            .get(&state)
+            .copied()
+            .unwrap_or_else(|| self.preset.style(state))
+            .animation()
+    }
+}
@@ -0,0 +1,129 @@
+//! Plays achievement audio handed off by the engine in fast lane mode, one
+//! bloop after the other, while the engine already handles the next card.
+
+use crate::audio::AudioPlayer;
+use crate::hardware::led::{Animation, LedController};
+use crate::palette::{Palette, StatusLed};
+use anyhow::{Error, Result};
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio_graceful_shutdown::{FutureExt, IntoSubsystem, SubsystemHandle};
+
+/// Number of bloops whose audio can wait to be played before the audio of
+/// further ones is dropped.
+pub const QUEUE_SIZE: usize = 4;
+
+/// Audio files of the achievements awarded by one bloop, `None` for
+/// achievements without audio.
+pub type Playback = Vec<Option<PathBuf>>;
+
+pub struct PlaybackTask {
+    playback_rx: mpsc::Receiver<Playback>,
+    audio_player: AudioPlayer,
+    led_controller: LedController,
+    /// Shown along with the award sound, unless disabled in the palette.
+    achievement_pulse: Option<Animation>,
+}
+
+impl PlaybackTask {
+    pub fn new(
+        playback_rx: mpsc::Receiver<Playback>,
+        audio_player: AudioPlayer,
+        led_controller: LedController,
+        palette: &Palette,
+    ) -> Self {
+        Self {
+            playback_rx,
+            audio_player,
+            led_controller,
+            achievement_pulse: palette
+                .achievement_pulse
+                .then(|| palette.animation(StatusLed::Achievement)),
+        }
+    }
+
+    async fn process(&mut self) -> Result<()> {
+        while let Some(playback) = self.playback_rx.recv().await {
+            for path in playback {
+                // The pulse returns to whatever the engine shows by then.
+                if let Some(pulse) = &self.achievement_pulse {
+                    self.led_controller.set_animation(pulse.clone()).await?;
+                }
+
+                self.audio_player.play_award().await?;
+
+                if let Some(path) = path {
+                    self.audio_player.play_achievement(path).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl IntoSubsystem<Error> for PlaybackTask {
+    async fn run(mut self, subsys: &mut SubsystemHandle) -> Result<()> {
+        if let Ok(result) = self.process().cancel_on_shutdown(subsys).await {
+            result?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::AudioCategory;
+    use crate::recording::{Event, Recorder};
+
+    async fn play(palette: &Palette, playback: Playback) -> Vec<Event> {
+        let data_dir = tempfile::tempdir().unwrap();
+        let recorder = Recorder::new();
+        let (playback_tx, playback_rx) = mpsc::channel(QUEUE_SIZE);
+        let mut task = PlaybackTask::new(
+            playback_rx,
+            recorder.audio_player(data_dir.path()).await.unwrap(),
+            recorder.led_controller(),
+            palette,
+        );
+
+        playback_tx.send(playback).await.unwrap();
+        drop(playback_tx);
+        task.process().await.unwrap();
+
+        recorder.events()
+    }
+
+    #[tokio::test]
+    async fn pulses_for_every_queued_achievement() {
+        let palette = Palette::default();
+        let events = play(&palette, vec![Some("cache/first.mp3".into()), None]).await;
+
+        assert_eq!(
+            events,
+            vec![
+                Event::Led(palette.animation(StatusLed::Achievement)),
+                Event::sound("awards/award.mp3", AudioCategory::Award),
+                Event::sound("cache/first.mp3", AudioCategory::Achievement),
+                Event::Led(palette.animation(StatusLed::Achievement)),
+                Event::sound("awards/award.mp3", AudioCategory::Award),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn plays_without_pulse_when_disabled() {
+        let palette: Palette = toml::from_str("achievement_pulse = false").unwrap();
+        let events = play(&palette, vec![Some("cache/first.mp3".into())]).await;
+
+        assert_eq!(
+            events,
+            vec![
+                Event::sound("awards/award.mp3", AudioCategory::Award),
+                Event::sound("cache/first.mp3", AudioCategory::Achievement),
+            ]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Config cards prepared for writing by the box.
+//!
+//! `bloop-box provision <payload>` stores a config payload in the data dir,
+//! so it has to be run with the data dir of the service. Presenting a `p`
+//! config card then makes the box write it to the next blank tag.
+
+use crate::hardware::data_path;
+use crate::ndef::{ConfigRecord, RecordKind};
+use anyhow::{bail, Context, Result};
+use std::io;
+use std::path::PathBuf;
+use tokio::fs;
+
+const FILENAME: &str = "provisioning.toml";
+
+const USAGE: &str = "usage: bloop-box provision [--kind text|mime|uri] <payload>
+       bloop-box provision --clear";
+
+async fn path() -> Result<PathBuf> {
+    Ok(data_path().await?.join(FILENAME))
+}
+
+/// Loads the prepared record, if there is one.
+pub async fn load() -> Result<Option<ConfigRecord>> {
+    let raw_toml = match fs::read_to_string(path().await?).await {
+        Ok(raw_toml) => raw_toml,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error).context("failed to read provisioning payload"),
+    };
+
+    Ok(Some(
+        toml::from_str(&raw_toml).context("failed to parse provisioning payload")?,
+    ))
+}
+
+async fn save(record: &ConfigRecord) -> Result<()> {
+    fs::write(path().await?, toml::to_string_pretty(record)?)
+        .await
+        .context("failed to write provisioning payload")
+}
+
+async fn clear() -> Result<()> {
+    match fs::remove_file(path().await?).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => {
+            Err(error).context("failed to remove provisioning payload")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Runs the `provision` command with the arguments following it.
+pub fn run_cli(args: &[String]) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    match args {
+        [flag] if flag == "--clear" => {
+            runtime.block_on(clear())?;
+            println!("provisioning payload cleared");
+            return Ok(());
+        }
+        [payload] => runtime.block_on(save(&ConfigRecord {
+            payload: payload.clone(),
+            kind: RecordKind::Text,
+        }))?,
+        [flag, kind, payload] if flag == "--kind" => {
+            let kind = match kind.as_str() {
+                "text" => RecordKind::Text,
+                "mime" => RecordKind::Mime,
+                "uri" => RecordKind::Uri,
+                kind => bail!("unknown record kind: {kind}\n{USAGE}"),
+            };
+
+            runtime.block_on(save(&ConfigRecord {
+                payload: payload.clone(),
+                kind,
+            }))?
+        }
+        _ => bail!(USAGE),
+    }
+
+    println!("provisioning payload stored, present a `p` config card to write it");
+    Ok(())
+}
@@ -0,0 +1,225 @@
+//! Recording outputs for tests.
+//!
+//! A [`Recorder`] serves as the LED and audio output, so tests can assert
+//! what the box showed and played, and in which order.
+
+use crate::audio::{AudioCategory, AudioCollection, AudioOutput, AudioPlayer, PlayError};
+use crate::hardware::led::{self, Animation, LedController, LedOutput};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Led(Animation),
+    Sound {
+        path: PathBuf,
+        category: AudioCategory,
+    },
+}
+
+impl Event {
+    pub fn sound<P: Into<PathBuf>>(path: P, category: AudioCategory) -> Self {
+        Self::Sound {
+            path: path.into(),
+            category,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    started: Instant,
+    events: Arc<Mutex<Vec<(Duration, Event)>>>,
+    missing_assets: Arc<Mutex<HashSet<PathBuf>>>,
+    output_missing: Arc<AtomicBool>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            events: Arc::new(Mutex::new(Vec::new())),
+            missing_assets: Arc::new(Mutex::new(HashSet::new())),
+            output_missing: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn led_controller(&self) -> LedController {
+        LedController::new(self.clone())
+    }
+
+    /// Creates a player with a single bloop and award sound.
+    pub async fn audio_player(&self, data_path: &Path) -> Result<AudioPlayer> {
+        AudioPlayer::with_output(
+            Arc::new(self.clone()),
+            data_path,
+            AudioCollection::new(vec!["bloops/bloop.mp3".into()])?,
+            AudioCollection::new(vec!["awards/award.mp3".into()])?,
+        )
+        .await
+    }
+
+    /// Makes the asset fail to open, as if the data package lacked it.
+    pub fn remove_asset<P: Into<PathBuf>>(&self, path: P) {
+        self.missing_assets.lock().unwrap().insert(path.into());
+    }
+
+    /// Makes the output check fail, as if no output device was found.
+    pub fn remove_output(&self) {
+        self.output_missing.store(true, Ordering::Relaxed);
+    }
+
+    pub fn record(&self, event: Event) {
+        self.events
+            .lock()
+            .unwrap()
+            .push((self.started.elapsed(), event));
+    }
+
+    /// Returns all recorded events in order.
+    pub fn events(&self) -> Vec<Event> {
+        self.timeline()
+            .into_iter()
+            .map(|(_, event)| event)
+            .collect()
+    }
+
+    /// Returns all recorded events with the time since the recorder was
+    /// created.
+    pub fn timeline(&self) -> Vec<(Duration, Event)> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl LedOutput for Recorder {
+    async fn show(&self, animation: Animation) -> Result<(), led::Error> {
+        self.record(Event::Led(animation));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AudioOutput for Recorder {
+    async fn check(&self) -> Result<()> {
+        if self.output_missing.load(Ordering::Relaxed) {
+            return Err(anyhow!("no output device"));
+        }
+
+        Ok(())
+    }
+
+    async fn play_file(
+        &self,
+        path: &Path,
+        category: AudioCategory,
+        _volume: f32,
+    ) -> Result<(), PlayError> {
+        self.record(Event::sound(path, category));
+        Ok(())
+    }
+
+    async fn play_asset(
+        &self,
+        path: &Path,
+        category: AudioCategory,
+        _volume: f32,
+    ) -> Result<(), PlayError> {
+        if self.missing_assets.lock().unwrap().contains(path) {
+            return Err(PlayError::MissingAsset(anyhow!(
+                "{} does not exist",
+                path.display()
+            )));
+        }
+
+        self.record(Event::sound(path, category));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::led::Color;
+    use crate::palette::{Palette, StatusLed};
+
+    #[tokio::test]
+    async fn records_led_states_and_sounds_in_order() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let recorder = Recorder::new();
+        let led_controller = recorder.led_controller();
+        let mut audio_player = recorder.audio_player(data_dir.path()).await.unwrap();
+        let palette = Palette::default();
+
+        led_controller
+            .set_animation(palette.animation(StatusLed::Busy))
+            .await
+            .unwrap();
+        audio_player.play_bloop().await.unwrap();
+        audio_player.play_error().await.unwrap();
+        led_controller
+            .set_animation(palette.animation(StatusLed::IdleConnected))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            recorder.events(),
+            vec![
+                Event::Led(Animation::solid(Color::MAGENTA)),
+                Event::sound("bloops/bloop.mp3", AudioCategory::Bloop),
+                Event::sound("error.mp3", AudioCategory::System),
+                Event::Led(Animation::solid(Color::GREEN)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn records_sequences_and_achievements() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let recorder = Recorder::new();
+        let mut audio_player = recorder.audio_player(data_dir.path()).await.unwrap();
+
+        audio_player.play_award().await.unwrap();
+        audio_player
+            .play_achievement("cache/achievement.mp3")
+            .await
+            .unwrap();
+        audio_player
+            .play_sequence(&["status/connected.mp3", "status/version.mp3"])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            recorder.events(),
+            vec![
+                Event::sound("awards/award.mp3", AudioCategory::Award),
+                Event::sound("cache/achievement.mp3", AudioCategory::Achievement),
+                Event::sound("status/connected.mp3", AudioCategory::System),
+                Event::sound("status/version.mp3", AudioCategory::System),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn timestamps_increase_monotonically() {
+        let recorder = Recorder::new();
+        let led_controller = recorder.led_controller();
+
+        led_controller.set_off().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        led_controller
+            .set_animation(Animation::solid(Color::RED))
+            .await
+            .unwrap();
+
+        let timeline = recorder.timeline();
+        assert_eq!(timeline.len(), 2);
+        assert!(timeline[1].0 - timeline[0].0 >= Duration::from_millis(10));
+    }
+}
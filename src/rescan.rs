@@ -0,0 +1,151 @@
+//! Guard against counting a card twice when it is lifted slightly and put
+//! back.
+//!
+//! After a counted card is removed, presenting it again within the window does
+//! not reach the server, which would only answer with a throttle.
+
+use crate::hardware::nfc::NfcUid;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RescanConfig {
+    /// Time after removal in which the same card is not counted again, zero
+    /// to disable the guard.
+    window_ms: u64,
+    response: RescanResponse,
+}
+
+impl Default for RescanConfig {
+    fn default() -> Self {
+        Self {
+            window_ms: 2000,
+            response: RescanResponse::default(),
+        }
+    }
+}
+
+/// How a card presented again within the window is answered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RescanResponse {
+    /// The card is not answered at all.
+    Ignore,
+    /// The card is answered with the "already counted" cue.
+    #[default]
+    Cue,
+}
+
+#[derive(Debug)]
+pub struct RescanGuard {
+    window: Duration,
+    response: RescanResponse,
+    /// Counted cards along with the time they were removed.
+    removed: HashMap<NfcUid, Instant>,
+}
+
+impl RescanGuard {
+    pub fn new(config: RescanConfig) -> Self {
+        Self {
+            window: Duration::from_millis(config.window_ms),
+            response: config.response,
+            removed: HashMap::new(),
+        }
+    }
+
+    /// Notes the removal of a counted card, which starts its window.
+    pub fn removed(&mut self, nfc_uid: NfcUid) {
+        if self.window.is_zero() {
+            return;
+        }
+
+        let now = Instant::now();
+        self.removed
+            .retain(|_, removed_at| now.duration_since(*removed_at) < self.window);
+        self.removed.insert(nfc_uid, now);
+    }
+
+    /// Returns how to answer the card if it is presented again within its
+    /// window.
+    pub fn check(&self, nfc_uid: &NfcUid) -> Option<RescanResponse> {
+        let removed_at = self.removed.get(nfc_uid)?;
+
+        (removed_at.elapsed() < self.window).then_some(self.response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+
+    const CARD: &str = "04a21f33445566";
+    const OTHER_CARD: &str = "04112233445566";
+
+    fn guard(window_ms: u64, response: RescanResponse) -> RescanGuard {
+        RescanGuard::new(RescanConfig {
+            window_ms,
+            response,
+        })
+    }
+
+    fn uid(hex: &str) -> NfcUid {
+        NfcUid::from_hex(hex).unwrap()
+    }
+
+    #[test]
+    fn answers_removed_cards_within_window() {
+        let mut guard = guard(60_000, RescanResponse::Cue);
+
+        assert_eq!(guard.check(&uid(CARD)), None);
+        guard.removed(uid(CARD));
+
+        assert_eq!(guard.check(&uid(CARD)), Some(RescanResponse::Cue));
+        assert_eq!(guard.check(&uid(OTHER_CARD)), None);
+    }
+
+    #[test]
+    fn answers_with_configured_response() {
+        let mut guard = guard(60_000, RescanResponse::Ignore);
+        guard.removed(uid(CARD));
+
+        assert_eq!(guard.check(&uid(CARD)), Some(RescanResponse::Ignore));
+    }
+
+    #[test]
+    fn counts_cards_again_after_window() {
+        let mut guard = guard(20, RescanResponse::Cue);
+        guard.removed(uid(CARD));
+        std::thread::sleep(Duration::from_millis(30));
+
+        assert_eq!(guard.check(&uid(CARD)), None);
+    }
+
+    #[test]
+    fn forgets_expired_removals() {
+        let mut guard = guard(20, RescanResponse::Cue);
+        guard.removed(uid(CARD));
+        std::thread::sleep(Duration::from_millis(30));
+        guard.removed(uid(OTHER_CARD));
+
+        assert_eq!(guard.removed.len(), 1);
+    }
+
+    #[test]
+    fn is_disabled_by_zero_window() {
+        let mut guard = guard(0, RescanResponse::Cue);
+        guard.removed(uid(CARD));
+
+        assert_eq!(guard.check(&uid(CARD)), None);
+    }
+
+    #[test]
+    fn defaults_to_cue_within_two_seconds() {
+        let config: RescanConfig = toml::from_str("").unwrap();
+
+        assert_eq!(config.window_ms, 2000);
+        assert_eq!(config.response, RescanResponse::Cue);
+    }
+}
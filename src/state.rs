@@ -1,4 +1,3 @@
-use crate::hardware::data_path;
 use anyhow::{Context, Result};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
@@ -22,8 +21,13 @@ impl<T> PersistedState<T>
 where
     T: Serialize + DeserializeOwned + Clone + Default + Send + Sync + 'static,
 {
-    pub async fn new(name: impl Into<String>, debounce: Option<Duration>) -> Result<Self> {
-        let data_path = data_path().await?;
+    /// Loads the state from the data dir, which it is persisted to on every
+    /// change.
+    pub async fn new(
+        data_path: &Path,
+        name: impl Into<String>,
+        debounce: Option<Duration>,
+    ) -> Result<Self> {
         let filename = format!("{}.state", name.into());
         let full_path = data_path.join(filename);
         let state = Self::load_state(&full_path).await;
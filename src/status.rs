@@ -0,0 +1,107 @@
+//! Spoken status readout.
+//!
+//! The readout is composed of clips from the `status` directory of the asset
+//! pack: the digits `0` to `9`, `dot`, and a few status words.
+
+use crate::network::NetworkStatus;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use tokio::net::UdpSocket;
+
+/// Builds the clip sequence describing the current state of the box.
+pub async fn status_clips(network_status: NetworkStatus) -> Vec<PathBuf> {
+    let mut clips = vec![clip("ip-address")];
+
+    match local_ip_address().await {
+        Some(address) => clips.extend(number_clips(&address.to_string())),
+        None => clips.push(clip("no-network")),
+    }
+
+    clips.push(clip("version"));
+    clips.extend(number_clips(env!("CARGO_PKG_VERSION")));
+
+    clips.push(clip(match network_status {
+        NetworkStatus::Connected { .. } => "connected",
+        NetworkStatus::Unconfigured => "unconfigured",
+        NetworkStatus::InvalidCredentials => "invalid-credentials",
+        NetworkStatus::Disconnected | NetworkStatus::Shutdown => "disconnected",
+    }));
+
+    clips
+}
+
+/// Spells out a dotted number such as an IPv4 address or a version.
+///
+/// Characters other than digits and dots are skipped.
+fn number_clips(number: &str) -> impl Iterator<Item = PathBuf> + '_ {
+    number.chars().filter_map(|c| match c {
+        '0'..='9' => Some(clip(c.encode_utf8(&mut [0; 4]))),
+        '.' => Some(clip("dot")),
+        _ => None,
+    })
+}
+
+fn clip(name: &str) -> PathBuf {
+    PathBuf::from("status").join(format!("{name}.mp3"))
+}
+
+/// Determines the address of the interface used for outgoing traffic.
+///
+/// Connecting a UDP socket only selects a route, no packets are sent.
+async fn local_ip_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await.ok()?;
+    socket.connect("192.0.2.1:9").await.ok()?;
+    let address = socket.local_addr().ok()?.ip();
+
+    (!address.is_unspecified()).then_some(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spelled(number: &str) -> Vec<String> {
+        number_clips(number)
+            .map(|clip| clip.file_stem().unwrap().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn spells_single_digits() {
+        assert_eq!(spelled("0"), ["0"]);
+        assert_eq!(spelled("7"), ["7"]);
+    }
+
+    #[test]
+    fn spells_teens_and_hundreds_digit_by_digit() {
+        assert_eq!(spelled("13"), ["1", "3"]);
+        assert_eq!(spelled("100"), ["1", "0", "0"]);
+        assert_eq!(spelled("255"), ["2", "5", "5"]);
+    }
+
+    #[test]
+    fn spells_large_and_dotted_numbers() {
+        assert_eq!(
+            spelled("4294967295"),
+            ["4", "2", "9", "4", "9", "6", "7", "2", "9", "5"]
+        );
+        assert_eq!(
+            spelled("192.168.0.10"),
+            ["1", "9", "2", "dot", "1", "6", "8", "dot", "0", "dot", "1", "0"]
+        );
+    }
+
+    #[test]
+    fn skips_other_characters() {
+        assert_eq!(spelled("v5.1-beta"), ["5", "dot", "1"]);
+        assert!(spelled("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn ends_with_connection_state() {
+        let clips = status_clips(NetworkStatus::InvalidCredentials).await;
+
+        assert_eq!(clips.first(), Some(&clip("ip-address")));
+        assert_eq!(clips.last(), Some(&clip("invalid-credentials")));
+    }
+}
@@ -0,0 +1,148 @@
+//! Reports to the systemd watchdog when running as a service with
+//! `WatchdogSec=` set.
+//!
+//! Keep-alive messages are sent by the engine loop, also while it plays audio
+//! or waits for cards, so systemd restarts the service if the loop stops being
+//! polled. Faults which the box recovers from by itself,
+//! like unresponsive readers, are only reported through the status.
+
+use std::env;
+use std::ffi::OsStr;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::select;
+use tokio::time::sleep;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Watchdog {
+    /// Interval at which keep-alives are sent, `None` if the watchdog is not
+    /// enabled.
+    interval: Option<Duration>,
+}
+
+impl Watchdog {
+    pub fn from_env() -> Self {
+        Self {
+            interval: parse_interval(env::var("WATCHDOG_USEC").ok().as_deref()),
+        }
+    }
+
+    pub fn keep_alive(&self) {
+        if self.interval.is_some() {
+            notify("WATCHDOG=1");
+        }
+    }
+
+    /// Completes once the next keep-alive is due, never if the watchdog is
+    /// not enabled.
+    pub async fn due(&self) {
+        match self.interval {
+            Some(interval) => sleep(interval).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Runs a future, sending keep-alives while it is pending.
+    pub async fn guard<F: Future>(&self, future: F) -> F::Output {
+        tokio::pin!(future);
+
+        loop {
+            select! {
+                output = &mut future => return output,
+                _ = self.due() => self.keep_alive(),
+            }
+        }
+    }
+
+    /// Reports the current fault, `None` once the box is healthy again.
+    pub fn set_fault(&self, fault: Option<&str>) {
+        if self.interval.is_none() {
+            return;
+        }
+
+        match fault {
+            Some(fault) => notify(&format!("STATUS={fault}")),
+            None => notify("STATUS=Running"),
+        }
+    }
+}
+
+/// Parses the watchdog timeout in microseconds into the keep-alive interval.
+fn parse_interval(watchdog_usec: Option<&str>) -> Option<Duration> {
+    watchdog_usec
+        .and_then(|usec| usec.parse().ok())
+        .filter(|&usec| usec > 0)
+        .map(Duration::from_micros)
+        // Keep-alives are sent at half the timeout, as recommended by systemd.
+        .map(|timeout| timeout / 2)
+}
+
+/// Sends a state to the service manager, if it provides a notify socket.
+fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    if let Err(error) = send_state(&path, state) {
+        warn!("failed to notify service manager: {}", error);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send_state(path: &OsStr, state: &str) -> io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+    let socket = UnixDatagram::unbound()?;
+
+    // A leading `@` denotes a socket in the abstract namespace.
+    match path.as_bytes().strip_prefix(b"@") {
+        Some(name) => socket.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?),
+        None => socket.send_to(state.as_bytes(), path),
+    }?;
+
+    Ok(())
+}
+
+/// There is no service manager to notify outside of Linux, where only the
+/// emulator runs.
+#[cfg(not(target_os = "linux"))]
+fn send_state(_path: &OsStr, _state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_interval() {
+        assert_eq!(
+            parse_interval(Some("60000000")),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(parse_interval(Some("0")), None);
+        assert_eq!(parse_interval(Some("60s")), None);
+        assert_eq!(parse_interval(None), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn notifies_abstract_socket() {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let name = format!("bloop-box-test-{}", std::process::id());
+        let socket =
+            UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+
+        send_state(OsStr::new(&format!("@{name}")), "WATCHDOG=1").unwrap();
+
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+    }
+}